@@ -1,42 +1,48 @@
-use rayon::iter::{
-    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
-};
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
+/// Narrows down which pairs of elements need to be tested against each other.
+pub trait Broadphase: Sync {
+    /// Calls `f` with every index that might interact with `i`. Over all `i` every
+    /// candidate pair has to be reported exactly once.
+    fn for_candidates(&self, i: usize, f: impl FnMut(usize));
+}
 
 pub trait ForPairs<E> {
-    fn par_for_pairs<T: Send>(
-        &mut self,
-        map: impl Fn(&E, &E) -> Option<(T, T)> + Sync,
-        apply: impl FnMut(&mut E, T),
-    );
+    /// Runs `map` on every candidate pair in parallel, and returns the results together with
+    /// the indices of the pair.
+    fn par_pairs<T: Send>(
+        &self,
+        broadphase: &impl Broadphase,
+        map: impl Fn(&E, &E) -> Option<T> + Sync,
+    ) -> Vec<(usize, usize, T)>;
 }
 
-impl<E: Sync> ForPairs<E> for Vec<E>
-where
-    Vec<E>: IntoParallelIterator<Item = E>,
-    <Vec<E> as IntoParallelIterator>::Iter: ParallelIterator + IndexedParallelIterator,
-{
-    fn par_for_pairs<T: Send>(
-        &mut self,
-        map: impl Fn(&E, &E) -> Option<(T, T)> + Sync,
-        mut apply: impl FnMut(&mut E, T),
-    ) {
+impl<E: Sync> ForPairs<E> for [E] {
+    fn par_pairs<T: Send>(
+        &self,
+        broadphase: &impl Broadphase,
+        map: impl Fn(&E, &E) -> Option<T> + Sync,
+    ) -> Vec<(usize, usize, T)> {
         let v = self
             .par_iter()
             .enumerate()
             .map(|(i, o0)| {
-                self[i + 1..]
-                    .iter()
-                    .enumerate()
-                    .filter_map(|(j, o1)| map(o0, o1).map(|(a, b)| ((i, a), (i + 1 + j, b))))
-                    .collect::<Vec<_>>()
+                let mut pairs = Vec::new();
+                broadphase.for_candidates(i, |j| {
+                    if let Some(t) = map(o0, &self[j]) {
+                        pairs.push((i, j, t));
+                    }
+                });
+                pairs
             })
             .collect::<Vec<_>>();
 
-        v.into_iter()
-            .flat_map(|v| v.into_iter())
-            .for_each(|((i, a), (j, b))| {
-                apply(&mut self[i], a);
-                apply(&mut self[j], b);
-            });
+        v.into_iter().flat_map(|v| v.into_iter()).collect()
     }
 }
+
+/// Sorts pairs by their indices, so applying them doesn't depend on the order the broadphase
+/// reported them in.
+pub fn sort_pairs<T>(pairs: &mut [(usize, usize, T)]) {
+    pairs.sort_unstable_by_key(|(i, j, _)| (*i.min(j), *i.max(j)));
+}
@@ -0,0 +1,21 @@
+#![feature(let_chains)]
+//! The physics simulation, either on its own through `Simulation` or inside a Bevy app
+//! through `PhysicsPlugin`.
+mod for_pairs;
+mod physics;
+
+use bevy::prelude::Color;
+
+pub use physics::*;
+
+/// What newly placed objects look like.
+pub struct PlacementSettings {
+    pub radius: f64,
+    pub color: Color,
+    pub density: f64,
+    pub material: Material,
+    pub drag: Option<Drag>,
+    pub kind: BodyKind,
+    pub ccd: bool,
+    pub charge: f64,
+}
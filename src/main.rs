@@ -1,6 +1,4 @@
 #![feature(let_chains)]
-mod for_pairs;
-mod physics;
 mod ui;
 
 use std::f64::consts::{FRAC_PI_2, TAU};
@@ -10,13 +8,14 @@ use bevy::{
     math::{DVec2, Vec3Swizzles, Vec4Swizzles},
     prelude::*,
 };
-use bevy_egui::EguiPlugin;
+use bevy_egui::{EguiContext, EguiPlugin};
 use bevy_pancam::{PanCam, PanCamPlugin};
-use physics::{Object, ObjectPos, PhysSettings, PhysicsPlugin};
+use physics::{
+    BodyKind, CollisionMode, GravityWell, LinkConstraint, Material, Object, ObjectBundle, ObjectPos,
+    PhysSettings, PhysicsPlugin, PlacementSettings, PointConstraint, SimRng, Wall,
+};
 use rand::Rng;
 
-use crate::physics::{LinkConstraint, ObjectBundle, PointConstraint};
-
 fn main() {
     App::new()
         .add_plugins(DefaultPlugins)
@@ -29,6 +28,11 @@ fn main() {
             radius: 4.0,
             color: Color::WHITE,
             density: 1.0,
+            material: Material::default(),
+            drag: None,
+            kind: BodyKind::Dynamic,
+            ccd: false,
+            charge: 0.0,
         })
         .add_startup_system(load_system)
         .add_system(input_system)
@@ -54,22 +58,22 @@ struct Chain {
     points: Vec<(f64, DVec2)>,
 }
 
-pub struct PlacementSettings {
-    radius: f64,
-    color: Color,
-    density: f64,
-}
-
 fn input_system(
     mut commands: Commands,
     settings: ResMut<PhysSettings>,
     placement: Res<PlacementSettings>,
     objects: Query<(Entity, &ObjectPos, &Object)>,
+    mut wells: Query<(Entity, &mut GravityWell)>,
     input: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    mut egui_context: ResMut<EguiContext>,
     windows: Res<Windows>,
     camera: Query<(&Camera, &GlobalTransform)>,
     circle: Res<Circle>,
+    mut rng: ResMut<SimRng>,
     mut chain_builder: Local<Option<Chain>>,
+    mut wall_builder: Local<Option<Vec<DVec2>>>,
+    mut dragged_well: Local<Option<Entity>>,
 ) {
     #[cfg(feature = "tracy")]
     profiling::scope!("input system");
@@ -81,9 +85,9 @@ fn input_system(
                 * Vec4::new(position.x, position.y, 0.0, 1.0)).xyz())
             .xy()
             .as_dvec2();
-            let mut rng = rand::thread_rng();
+            let rng = &mut rng.0;
             if input.pressed(KeyCode::Space) {
-                let pos = if settings.collisions && input.pressed(KeyCode::LControl) {
+                let pos = if settings.collisions != CollisionMode::Off && input.pressed(KeyCode::LControl) {
                     let mut moved = false;
                     let mut pos = pos;
                     let mut last_angle: Option<f64> = None;
@@ -107,11 +111,11 @@ fn input_system(
                 } else {
                     pos
                 };
-                if !settings.collisions || objects.iter().all(|(_, p, o)| {
+                if settings.collisions == CollisionMode::Off || objects.iter().all(|(_, p, o)| {
                     let r = placement.radius + o.radius;
                     p.current.distance_squared(pos) > r * r
                 }) {
-                    commands.spawn_bundle(ObjectBundle::new(pos, &placement, circle.0.clone()));
+                    ObjectBundle::spawn(&mut commands, pos, &placement, circle.0.clone());
                 }
             }
             if input.pressed(KeyCode::Q) {
@@ -155,15 +159,17 @@ fn input_system(
                     }
                 }
                 if input.just_released(KeyCode::C) {
-                    let chain_obj = |pos| ObjectBundle::new(pos, &placement, circle.0.clone());
+                    let chain_obj = |commands: &mut Commands, pos| {
+                        ObjectBundle::spawn(commands, pos, &placement, circle.0.clone())
+                    };
                     let mut last = chain.start.map(|p| {
-                        let id = commands.spawn_bundle(chain_obj(p)).id();
+                        let id = chain_obj(&mut commands, p);
                         commands.spawn().insert(PointConstraint::new(id, p, 0.0));
                         id
                     });
                     let mut last_l = None;
                     for (dist, p) in &chain.points {
-                        let id = commands.spawn_bundle(chain_obj(*p)).id();
+                        let id = chain_obj(&mut commands, *p);
                         if let Some(last) = last {
                             commands.spawn().insert(LinkConstraint::new(last, id, *dist * 1.01, *dist * 10.0));
                         }
@@ -176,6 +182,43 @@ fn input_system(
                     *chain_builder = None;
                 }
             }
+
+            // Clicks on the settings panel are for the panel.
+            let clicked = mouse.just_pressed(MouseButton::Left)
+                && !egui_context.ctx_mut().wants_pointer_input();
+
+            // Hold W to draw a wall from where it was pressed, clicking adds corners.
+            if input.just_pressed(KeyCode::W) {
+                *wall_builder = Some(vec![pos]);
+            }
+            if let Some(wall) = &mut *wall_builder {
+                if clicked {
+                    wall.push(pos);
+                }
+                if input.just_released(KeyCode::W) {
+                    let mut points = std::mem::take(wall);
+                    points.push(pos);
+                    points.dedup();
+                    if points.len() > 1 {
+                        Wall::spawn(&mut commands, points, placement.color);
+                    }
+                    *wall_builder = None;
+                }
+            }
+
+            // G places a gravity well, wells can be dragged around with the left mouse button.
+            if input.just_pressed(KeyCode::G) {
+                GravityWell::spawn(&mut commands, GravityWell::new(pos), circle.0.clone());
+            }
+            if wall_builder.is_none() && clicked {
+                *dragged_well = wells.iter().find(|(_, w)| w.contains(pos)).map(|(e, _)| e);
+            }
+            if mouse.just_released(MouseButton::Left) {
+                *dragged_well = None;
+            }
+            if let Some(e) = *dragged_well && let Ok((_, mut well)) = wells.get_mut(e) {
+                well.pos = pos;
+            }
         }
     }
 }
@@ -0,0 +1,415 @@
+use bevy::math::DVec2;
+use rand::Rng;
+
+use super::PhysObject;
+
+#[derive(Clone)]
+pub enum Bounds {
+    Circle(f64),
+    Rect(DVec2, DVec2),
+    /// Simple polygon, the vertices can be in either winding order.
+    Polygon(Vec<DVec2>),
+    /// Anywhere inside either of the bounds.
+    Union(Box<Bounds>, Box<Bounds>),
+    /// Only inside both of the bounds.
+    Intersection(Box<Bounds>, Box<Bounds>),
+    /// Inside the first and outside the second, two circles make an annulus.
+    Exclusion(Box<Bounds>, Box<Bounds>),
+    /// Objects leaving one side come back in on the opposite one.
+    Periodic(DVec2, DVec2),
+    None,
+}
+
+impl Bounds {
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub(super) fn random_point(&self, rng: &mut impl Rng, radius: f64) -> DVec2 {
+        match self {
+            Bounds::Circle(r) => {
+                let angle = rng.gen_range(0.0..2.0 * std::f64::consts::PI);
+                let r = rng.gen_range(0.0..r - radius);
+                DVec2::new(angle.cos() * r, angle.sin() * r)
+            }
+            Bounds::Rect(min, max) => {
+                let x = rng.gen_range(min.x + radius..max.x - radius);
+                let y = rng.gen_range(min.y + radius..max.y - radius);
+                DVec2::new(x, y)
+            }
+            Bounds::Periodic(min, max) => {
+                DVec2::new(rng.gen_range(min.x..max.x), rng.gen_range(min.y..max.y))
+            }
+            Bounds::None => DVec2::new(0.0, 0.0),
+            _ => {
+                // Rejection sampling in the bounding box, falls back to its center.
+                let (min, max) = self.bounding_box();
+                let (min, max) = (min + radius, max - radius);
+                if min.cmplt(max).all() {
+                    for _ in 0..1000 {
+                        let p = DVec2::new(rng.gen_range(min.x..max.x), rng.gen_range(min.y..max.y));
+                        if self.distance(p).0 + radius <= 0.0 {
+                            return p;
+                        }
+                    }
+                }
+                (min + max) / 2.0
+            }
+        }
+    }
+
+    /// Box containing everything inside the bounds.
+    fn bounding_box(&self) -> (DVec2, DVec2) {
+        match self {
+            Bounds::Circle(r) => (DVec2::splat(-r), DVec2::splat(*r)),
+            Bounds::Rect(min, max) | Bounds::Periodic(min, max) => (*min, *max),
+            Bounds::Polygon(points) => points.iter().fold(
+                (DVec2::splat(f64::INFINITY), DVec2::splat(f64::NEG_INFINITY)),
+                |(min, max), p| (min.min(*p), max.max(*p)),
+            ),
+            Bounds::Union(a, b) => {
+                let (a, b) = (a.bounding_box(), b.bounding_box());
+                (a.0.min(b.0), a.1.max(b.1))
+            }
+            Bounds::Intersection(a, b) => {
+                let (a, b) = (a.bounding_box(), b.bounding_box());
+                (a.0.max(b.0), a.1.min(b.1))
+            }
+            Bounds::Exclusion(a, _) => a.bounding_box(),
+            Bounds::None => (DVec2::splat(f64::NEG_INFINITY), DVec2::splat(f64::INFINITY)),
+        }
+    }
+
+    /// Signed distance to the edge of the bounds, negative inside, and the direction in which
+    /// it grows fastest.
+    fn distance(&self, p: DVec2) -> (f64, DVec2) {
+        match self {
+            Bounds::Circle(r) => {
+                let l = p.length();
+                let dir = if l == 0.0 { DVec2::X } else { p / l };
+                (l - r, dir)
+            }
+            Bounds::Rect(min, max) => {
+                let center = (*min + *max) / 2.0;
+                let offset = p - center;
+                let q = offset.abs() - (*max - *min) / 2.0;
+                let sign = DVec2::select(offset.cmplt(DVec2::ZERO), -DVec2::ONE, DVec2::ONE);
+                if q.max_element() > 0.0 {
+                    let outside = q.max(DVec2::ZERO);
+                    let l = outside.length();
+                    (l, outside / l * sign)
+                } else if q.x > q.y {
+                    (q.x, DVec2::new(sign.x, 0.0))
+                } else {
+                    (q.y, DVec2::new(0.0, sign.y))
+                }
+            }
+            Bounds::Polygon(points) => {
+                let mut closest = (f64::INFINITY, DVec2::ZERO);
+                let mut inside = false;
+                for (i, a) in points.iter().enumerate() {
+                    let b = points[(i + 1) % points.len()];
+                    let axis = b - *a;
+                    let len_sqr = axis.length_squared();
+                    let t = if len_sqr == 0.0 {
+                        0.0
+                    } else {
+                        ((p - *a).dot(axis) / len_sqr).clamp(0.0, 1.0)
+                    };
+                    let offset = p - (*a + axis * t);
+                    let dist_sqr = offset.length_squared();
+                    if dist_sqr < closest.0 {
+                        closest = (dist_sqr, offset);
+                    }
+                    // Even-odd rule
+                    if (a.y > p.y) != (b.y > p.y)
+                        && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x)
+                    {
+                        inside = !inside;
+                    }
+                }
+                let dist = closest.0.sqrt();
+                let dir = closest.1.normalize_or_zero();
+                if inside {
+                    (-dist, -dir)
+                } else {
+                    (dist, dir)
+                }
+            }
+            Bounds::Union(a, b) => {
+                let (a, b) = (a.distance(p), b.distance(p));
+                if a.0 < b.0 {
+                    a
+                } else {
+                    b
+                }
+            }
+            Bounds::Intersection(a, b) => {
+                let (a, b) = (a.distance(p), b.distance(p));
+                if a.0 > b.0 {
+                    a
+                } else {
+                    b
+                }
+            }
+            Bounds::Exclusion(a, b) => {
+                let (a, b) = (a.distance(p), b.distance(p));
+                if a.0 > -b.0 {
+                    a
+                } else {
+                    (-b.0, -b.1)
+                }
+            }
+            // Nothing is ever outside of these.
+            Bounds::Periodic(_, _) | Bounds::None => (f64::NEG_INFINITY, DVec2::ZERO),
+        }
+    }
+
+    /// Whether `p` is inside the bounds. Periodic bounds only contain their rectangle here,
+    /// unlike when they bound the simulation, so they can be used as regions.
+    pub(super) fn contains(&self, p: DVec2) -> bool {
+        match self {
+            Bounds::Periodic(min, max) => p.cmpge(*min).all() && p.cmple(*max).all(),
+            Bounds::Union(a, b) => a.contains(p) || b.contains(p),
+            Bounds::Intersection(a, b) => a.contains(p) && b.contains(p),
+            Bounds::Exclusion(a, b) => a.contains(p) && !b.contains(p),
+            _ => self.distance(p).0 <= 0.0,
+        }
+    }
+
+    /// Direction pointing into the bounds from the nearest edge.
+    #[inline(always)]
+    pub(super) fn normal(&self, p: DVec2) -> DVec2 {
+        -self.distance(p).1
+    }
+
+    #[inline(always)]
+    pub(super) fn update_position(&self, obj: &mut PhysObject) {
+        match self {
+            Bounds::Circle(r) => {
+                let l = obj.pos.length();
+                if l > r - obj.radius {
+                    let velocity = obj.pos - obj.pos_old;
+                    let normal = -obj.pos / l;
+                    obj.pos = obj.pos / l * (r - obj.radius);
+                    obj.bounce(normal, velocity);
+                }
+            }
+            Bounds::Rect(min, max) => {
+                let r = DVec2::splat(obj.radius);
+                let clamped = obj.pos.clamp(*min + r, *max - r);
+                if clamped != obj.pos {
+                    let velocity = obj.pos - obj.pos_old;
+                    let normal = (clamped - obj.pos).normalize();
+                    obj.pos = clamped;
+                    obj.bounce(normal, velocity);
+                }
+            }
+            Bounds::Periodic(min, max) => {
+                let size = *max - *min;
+                let shift = ((obj.pos - *min) / size).floor() * size;
+                if shift != DVec2::ZERO {
+                    obj.pos -= shift;
+                    obj.pos_old -= shift;
+                }
+            }
+            Bounds::None => {}
+            _ => {
+                let velocity = obj.pos - obj.pos_old;
+                let mut pushed = None;
+                // A second pass takes care of corners where two edges meet.
+                for _ in 0..2 {
+                    let (dist, dir) = self.distance(obj.pos);
+                    let depth = dist + obj.radius;
+                    if depth <= 0.0 || dir == DVec2::ZERO {
+                        break;
+                    }
+                    obj.pos -= dir * depth;
+                    pushed = Some(pushed.unwrap_or(DVec2::ZERO) - dir);
+                }
+                if let Some(normal) = pushed {
+                    obj.bounce(normal.normalize_or_zero(), velocity);
+                }
+            }
+        }
+    }
+
+    /// When an object moving from `from` to `to` would leave the bounds, as a fraction of
+    /// the movement.
+    pub(super) fn time_of_impact(&self, from: DVec2, to: DVec2, radius: f64) -> Option<f64> {
+        let d = to - from;
+        match self {
+            Bounds::Circle(r) => {
+                let l = r - radius;
+                let c = from.length_squared() - l * l;
+                let a = d.length_squared();
+                if c > 0.0 || a == 0.0 || to.length_squared() <= l * l {
+                    return None;
+                }
+                let b = 2.0 * from.dot(d);
+                let t = (-b + (b * b - 4.0 * a * c).sqrt()) / (2.0 * a);
+                (0.0..=1.0).contains(&t).then_some(t)
+            }
+            Bounds::Rect(min, max) => {
+                let r = DVec2::splat(radius);
+                let (min, max) = (*min + r, *max - r);
+                if from.cmpge(min).all() && from.cmple(max).all() {
+                    let mut t = f64::INFINITY;
+                    for axis in 0..2 {
+                        if to[axis] > max[axis] {
+                            t = t.min((max[axis] - from[axis]) / d[axis]);
+                        } else if to[axis] < min[axis] {
+                            t = t.min((min[axis] - from[axis]) / d[axis]);
+                        }
+                    }
+                    (t <= 1.0).then_some(t)
+                } else {
+                    None
+                }
+            }
+            Bounds::Periodic(_, _) | Bounds::None => None,
+            _ => {
+                let outside = |p: DVec2| self.distance(p).0 + radius > 0.0;
+                if outside(from) || !outside(to) {
+                    return None;
+                }
+                // Bisect for the last point still inside.
+                let (mut inside, mut out) = (0.0, 1.0);
+                for _ in 0..16 {
+                    let t = (inside + out) / 2.0;
+                    if outside(from + d * t) {
+                        out = t;
+                    } else {
+                        inside = t;
+                    }
+                }
+                Some(inside)
+            }
+        }
+    }
+
+    /// Shortest vector equivalent to `axis`, which only differs from it for periodic bounds.
+    #[inline(always)]
+    pub(super) fn min_image(&self, axis: DVec2) -> DVec2 {
+        match self {
+            Bounds::Periodic(min, max) => {
+                let size = *max - *min;
+                axis - (axis / size).round() * size
+            }
+            _ => axis,
+        }
+    }
+
+    /// Offsets of the copies of the space that can be within `margin` of a point inside it.
+    pub(super) fn images(&self, pos: DVec2, margin: f64) -> impl Iterator<Item = DVec2> {
+        let (min, max) = match self {
+            Bounds::Periodic(min, max) => (*min, *max),
+            _ => (DVec2::ZERO, DVec2::ZERO),
+        };
+        let size = max - min;
+        let side = |p: f64, min: f64, max: f64, size: f64| {
+            if p - min < margin {
+                size
+            } else if max - p < margin {
+                -size
+            } else {
+                0.0
+            }
+        };
+        let x = side(pos.x, min.x, max.x, size.x);
+        let y = side(pos.y, min.y, max.y, size.y);
+        [
+            (DVec2::new(x, 0.0), x != 0.0),
+            (DVec2::new(0.0, y), y != 0.0),
+            (DVec2::new(x, y), x != 0.0 && y != 0.0),
+        ]
+        .into_iter()
+        .filter_map(|(offset, near)| near.then_some(offset))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Bounds::Circle(_) => "Circle",
+            Bounds::Rect(_, _) => "Rect",
+            Bounds::Polygon(_) => "Polygon",
+            Bounds::Union(_, _) => "Union",
+            Bounds::Intersection(_, _) => "Intersection",
+            Bounds::Exclusion(_, _) => "Exclusion",
+            Bounds::Periodic(_, _) => "Periodic",
+            Bounds::None => "None",
+        }
+    }
+
+    pub fn from_str(s: &str, bounds: Bounds) -> Bounds {
+        match s {
+            "Circle" => {
+                if matches!(bounds, Bounds::Circle(_)) {
+                    bounds
+                } else {
+                    Bounds::Circle(200.0)
+                }
+            }
+            "Rect" => {
+                if matches!(bounds, Bounds::Rect(_, _)) {
+                    bounds
+                } else {
+                    Bounds::Rect(DVec2::new(-100.0, -100.0), DVec2::new(100.0, 100.0))
+                }
+            }
+            "Polygon" => {
+                if matches!(bounds, Bounds::Polygon(_)) {
+                    bounds
+                } else {
+                    Bounds::Polygon(vec![
+                        DVec2::new(-200.0, -100.0),
+                        DVec2::new(200.0, -100.0),
+                        DVec2::new(0.0, 200.0),
+                    ])
+                }
+            }
+            "Union" => {
+                if matches!(bounds, Bounds::Union(_, _)) {
+                    bounds
+                } else {
+                    Bounds::Union(
+                        Box::new(Bounds::Circle(200.0)),
+                        Box::new(Bounds::Rect(
+                            DVec2::new(0.0, -100.0),
+                            DVec2::new(400.0, 100.0),
+                        )),
+                    )
+                }
+            }
+            "Intersection" => {
+                if matches!(bounds, Bounds::Intersection(_, _)) {
+                    bounds
+                } else {
+                    Bounds::Intersection(
+                        Box::new(Bounds::Circle(200.0)),
+                        Box::new(Bounds::Rect(
+                            DVec2::new(-150.0, -150.0),
+                            DVec2::new(150.0, 150.0),
+                        )),
+                    )
+                }
+            }
+            "Exclusion" => {
+                if matches!(bounds, Bounds::Exclusion(_, _)) {
+                    bounds
+                } else {
+                    Bounds::Exclusion(
+                        Box::new(Bounds::Circle(200.0)),
+                        Box::new(Bounds::Circle(100.0)),
+                    )
+                }
+            }
+            "Periodic" => {
+                if matches!(bounds, Bounds::Periodic(_, _)) {
+                    bounds
+                } else {
+                    Bounds::Periodic(DVec2::new(-200.0, -200.0), DVec2::new(200.0, 200.0))
+                }
+            }
+            "None" => Bounds::None,
+            _ => bounds,
+        }
+    }
+}
@@ -4,10 +4,16 @@ use bevy::{math::DVec2, prelude::*};
 
 use super::PhysObject;
 
+/// `image` maps the vector between two points to the shortest equivalent one, see
+/// `Bounds::min_image`.
 pub trait Constraint<E> {
     type This<U>;
-    fn apply<'a, C: IndexMut<E, Output = PhysObject>>(&self, get: &mut C);
-    fn should_stay<F: Fn(&E) -> DVec2>(&self, get: F) -> bool;
+    fn apply<'a, C: IndexMut<E, Output = PhysObject>>(
+        &self,
+        get: &mut C,
+        image: impl Fn(DVec2) -> DVec2,
+    );
+    fn should_stay<F: Fn(&E) -> DVec2>(&self, get: F, image: impl Fn(DVec2) -> DVec2) -> bool;
     fn try_map<T, F: FnMut(&E) -> Option<T>>(&self, map: F) -> Option<Self::This<T>>;
 }
 
@@ -17,24 +23,66 @@ pub struct LinkConstraint<E> {
     b: E,
     dist: f64,
     snap: f64,
+    snapped: bool,
 }
 
-impl LinkConstraint<Entity> {
-    pub fn new(a: Entity, b: Entity, dist: f64, snap: f64) -> Self {
+impl<E> LinkConstraint<E> {
+    pub fn new(a: E, b: E, dist: f64, snap: f64) -> Self {
         Self {
             a,
             b,
             dist,
             snap,
+            snapped: false,
         }
     }
+
+    /// Whether the link has been stretched past its snapping distance, snapped links don't
+    /// do anything anymore.
+    pub fn is_snapped(&self) -> bool {
+        self.snapped
+    }
+
+    pub fn snap(&mut self) {
+        self.snapped = true;
+    }
+
+    pub fn ends(&self) -> (&E, &E) {
+        (&self.a, &self.b)
+    }
+
+    /// Moves the ends attached to `from` over to `to`, a link left with both ends on the same
+    /// object snaps.
+    pub fn retarget(&mut self, from: E, to: E)
+    where
+        E: PartialEq + Copy,
+    {
+        if self.a == from {
+            self.a = to;
+        }
+        if self.b == from {
+            self.b = to;
+        }
+        if self.a == self.b {
+            self.snapped = true;
+        }
+    }
+
+    /// How much longer the link is than its rest length.
+    pub fn stretch(&self, get: impl Fn(&E) -> DVec2, image: impl Fn(DVec2) -> DVec2) -> f64 {
+        image(get(&self.a) - get(&self.b)).length() - self.dist
+    }
 }
 
 impl<E: Copy> Constraint<E> for LinkConstraint<E> {
     type This<U> = LinkConstraint<U>;
 
-    fn apply<'a, C: IndexMut<E, Output = PhysObject>>(&self, get: &mut C) {
-        let axis = get[self.a].pos - get[self.b].pos;
+    fn apply<'a, C: IndexMut<E, Output = PhysObject>>(
+        &self,
+        get: &mut C,
+        image: impl Fn(DVec2) -> DVec2,
+    ) {
+        let axis = image(get[self.a].pos - get[self.b].pos);
         let axis = if axis == DVec2::ZERO {
             DVec2::new(f64::EPSILON, f64::EPSILON)
         } else {
@@ -43,10 +91,13 @@ impl<E: Copy> Constraint<E> for LinkConstraint<E> {
         let dist = axis.length();
         let n = axis / dist;
         let delta = self.dist - dist;
-        let a_m = get[self.a].mass;
-        let b_m = get[self.b].mass;
-        get[self.a].pos += n * (delta * b_m / (a_m + b_m));
-        get[self.b].pos -= n * (delta * a_m / (a_m + b_m));
+        let a_inv = get[self.a].inv_mass();
+        let b_inv = get[self.b].inv_mass();
+        if a_inv + b_inv == 0.0 {
+            return;
+        }
+        get[self.a].pos += n * (delta * a_inv / (a_inv + b_inv));
+        get[self.b].pos -= n * (delta * b_inv / (a_inv + b_inv));
         #[cfg(feature = "panic-nan")]
         {
             get[self.a].panic_nan("link");
@@ -61,12 +112,13 @@ impl<E: Copy> Constraint<E> for LinkConstraint<E> {
                 b,
                 dist: self.dist,
                 snap: self.snap,
+                snapped: self.snapped,
             })
         })
     }
 
-    fn should_stay<F: Fn(&E) -> DVec2>(&self, get: F) -> bool {
-        get(&self.a).distance_squared(get(&self.b)) < self.snap * self.snap
+    fn should_stay<F: Fn(&E) -> DVec2>(&self, get: F, image: impl Fn(DVec2) -> DVec2) -> bool {
+        image(get(&self.a) - get(&self.b)).length_squared() < self.snap * self.snap
     }
 }
 
@@ -78,6 +130,23 @@ pub struct PointConstraint<E> {
 }
 
 impl<E> PointConstraint<E> {
+    pub fn new(a: E, point: DVec2, dist: f64) -> Self {
+        Self {
+            a,
+            point,
+            dist,
+        }
+    }
+
+    pub fn retarget(&mut self, from: E, to: E)
+    where
+        E: PartialEq,
+    {
+        if self.a == from {
+            self.a = to;
+        }
+    }
+
     pub fn try_map<T>(&self, mut map: impl FnMut(&E) -> Option<T>) -> Option<PointConstraint<T>> {
         map(&self.a).map(|a| PointConstraint {
             a,
@@ -87,25 +156,19 @@ impl<E> PointConstraint<E> {
     }
 }
 
-impl PointConstraint<Entity> {
-    pub fn new(a: Entity, point: DVec2, dist: f64) -> Self {
-        Self {
-            a,
-            point,
-            dist,
-        }
-    }
-}
-
 impl<E: Copy> Constraint<E> for PointConstraint<E> {
     type This<U> = PointConstraint<U>;
 
-    fn apply<'a, C: IndexMut<E, Output = PhysObject>>(&self, get: &mut C) {
-            let axis = get[self.a].pos - self.point;
+    fn apply<'a, C: IndexMut<E, Output = PhysObject>>(
+        &self,
+        get: &mut C,
+        image: impl Fn(DVec2) -> DVec2,
+    ) {
+            let axis = image(get[self.a].pos - self.point);
             let dist = axis.length();
             let n = axis / dist;
             let delta = self.dist - dist;
-            let p = self.point + n * delta;
+            let p = get[self.a].pos - axis + n * delta;
             get[self.a].pos = p;
             get[self.a].pos_old = p;
             get[self.a].acceleration = DVec2::ZERO;
@@ -121,7 +184,7 @@ impl<E: Copy> Constraint<E> for PointConstraint<E> {
         })
     }
 
-    fn should_stay<F: Fn(&E) -> DVec2>(&self, _: F) -> bool {
+    fn should_stay<F: Fn(&E) -> DVec2>(&self, _: F, _: impl Fn(DVec2) -> DVec2) -> bool {
         true
     }
 }
@@ -0,0 +1,99 @@
+use bevy::math::DVec2;
+
+use super::{forces, Simulation};
+
+/// Conserved quantities of the simulation, updated after every physics frame.
+#[derive(Clone, Copy, Default)]
+pub struct PhysDiagnostics {
+    pub kinetic_energy: f64,
+    /// Gravitational and electric potential energy between objects, summed like
+    /// `PhysSettings::n_body`, and of objects in gravity wells.
+    pub potential_energy: f64,
+    /// Furthest any link is from its rest length. Links are rigid, so this shows how well
+    /// they hold rather than any stored energy.
+    pub max_link_stretch: f64,
+    pub momentum: DVec2,
+    /// About the center of mass.
+    pub angular_momentum: f64,
+    pub center_of_mass: DVec2,
+    pub mass: f64,
+}
+
+impl PhysDiagnostics {
+    pub fn total_energy(&self) -> f64 {
+        self.kinetic_energy + self.potential_energy
+    }
+
+    /// Measures `sim`, `dt` is the length of one sub step.
+    pub fn new(sim: &Simulation, dt: f64) -> Self {
+        #[cfg(feature = "tracy")]
+        profiling::scope!("diagnostics");
+
+        let objects = &sim.objects;
+        let image = |axis| sim.settings.bounds.min_image(axis);
+        let velocity = |i: usize| (objects[i].pos - objects[i].pos_old) / dt;
+
+        let mut diagnostics = PhysDiagnostics::default();
+        for (i, obj) in objects.iter().enumerate() {
+            let v = velocity(i);
+            diagnostics.kinetic_energy += 0.5 * obj.mass * v.length_squared();
+            diagnostics.momentum += obj.mass * v;
+            diagnostics.center_of_mass += obj.mass * obj.pos;
+            diagnostics.mass += obj.mass;
+        }
+        if diagnostics.mass > 0.0 {
+            diagnostics.center_of_mass /= diagnostics.mass;
+        }
+        let com = diagnostics.center_of_mass;
+        diagnostics.angular_momentum = objects
+            .iter()
+            .enumerate()
+            .map(|(i, obj)| obj.mass * (obj.pos - com).perp_dot(velocity(i)))
+            .sum();
+
+        let settings = &sim.settings;
+        let positions = objects.iter().map(|o| o.pos).collect::<Vec<_>>();
+        // Every pair is counted from both sides, and every object also sits in its own
+        // potential, which is finite with softening and taken out again.
+        if settings.gravitational_constant.abs() > f64::EPSILON {
+            let force = settings.n_body_force();
+            let bodies = objects.iter().map(|o| (o.pos, o.mass)).collect::<Vec<_>>();
+            let potential = forces::potential(settings, force, &bodies, &positions);
+            diagnostics.potential_energy += 0.5
+                * potential
+                    .iter()
+                    .zip(objects)
+                    .map(|(p, o)| (p - force.potential(DVec2::ZERO, o.mass)) * o.mass)
+                    .sum::<f64>();
+        }
+        if settings.coulomb_constant.abs() > f64::EPSILON {
+            let force = settings.coulomb_force();
+            let (positive, negative) = forces::charges(objects, &positions);
+            let positive = forces::potential(settings, force, &positive, &positions);
+            let negative = forces::potential(settings, force, &negative, &positions);
+            diagnostics.potential_energy += 0.5
+                * positive
+                    .iter()
+                    .zip(negative)
+                    .zip(objects)
+                    .map(|((p, n), o)| {
+                        (p - n - force.potential(DVec2::ZERO, o.charge)) * o.charge
+                    })
+                    .sum::<f64>();
+        }
+        diagnostics.potential_energy += sim
+            .wells
+            .iter()
+            .map(|w| objects.iter().map(|o| o.mass * w.potential(o.pos, image)).sum::<f64>())
+            .sum::<f64>();
+
+        diagnostics.max_link_stretch = sim
+            .links
+            .iter()
+            .filter(|l| !l.is_snapped())
+            .map(|link| link.stretch(|i| objects[*i].pos, image).abs())
+            .fold(0.0, f64::max);
+
+        diagnostics
+    }
+}
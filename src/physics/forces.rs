@@ -0,0 +1,203 @@
+use bevy::math::DVec2;
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
+use super::{
+    nbody::{NBody, PairForce, Quadtree},
+    well::Well,
+    Integrator, PhysObject, PhysSettings,
+};
+
+/// Acceleration of every object from gravity, charges, the magnetic field, the force fields
+/// and `wells`, if there is any. Sleeping objects are left at zero.
+///
+/// `states` holds the position and velocity to evaluate each object at, and `time` the
+/// simulation time, which lets integrators sample the forces in between steps.
+pub(super) fn accelerations(
+    settings: &PhysSettings,
+    wells: &[Well],
+    objects: &[PhysObject],
+    states: &[(DVec2, DVec2)],
+    time: f64,
+) -> Option<Vec<DVec2>> {
+    #[cfg(feature = "tracy")]
+    profiling::scope!("accelerations");
+
+    let n_body = settings.gravitational_constant.abs() > f64::EPSILON;
+    let charged = objects.iter().any(|o| o.charge != 0.0);
+    let coulomb = charged && settings.coulomb_constant.abs() > f64::EPSILON;
+    // Position Verlet turns the velocities itself, see `MagneticField::rotate`.
+    let magnetic = charged
+        && settings.magnetic_field.is_active()
+        && settings.integrator != Integrator::PositionVerlet;
+    let field = settings.fields.iter().any(|f| f.enabled);
+    if !n_body && !coulomb && !magnetic && !field && wells.is_empty() {
+        return None;
+    }
+
+    let positions = states.iter().map(|(pos, _)| *pos).collect::<Vec<_>>();
+    let mut acc = vec![DVec2::ZERO; objects.len()];
+    // Sleeping objects still pull on the others, but nothing is summed up for them.
+    let awake = objects
+        .iter()
+        .enumerate()
+        .filter(|(_, o)| !o.is_asleep())
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let targets = awake.iter().map(|i| positions[*i]).collect::<Vec<_>>();
+
+    if n_body {
+        let bodies = positions
+            .iter()
+            .zip(objects)
+            .map(|(pos, o)| (*pos, o.mass))
+            .collect::<Vec<_>>();
+        let gravity = pull(settings, settings.n_body_force(), &bodies, &targets);
+        for (i, a) in awake.iter().zip(gravity) {
+            acc[*i] += a;
+        }
+    }
+
+    if coulomb {
+        let field = electric_field(settings, objects, &positions, &targets);
+        for (i, e) in awake.iter().zip(field) {
+            let obj = &objects[*i];
+            if obj.mass > 0.0 {
+                acc[*i] += e * (obj.charge / obj.mass);
+            }
+        }
+    }
+
+    if magnetic {
+        for i in awake.iter().copied() {
+            let ((pos, velocity), obj) = (states[i], &objects[i]);
+            if obj.charge != 0.0 && obj.mass > 0.0 {
+                acc[i] += settings
+                    .magnetic_field
+                    .acceleration(pos, velocity, time, obj.charge / obj.mass);
+            }
+        }
+    }
+
+    if field {
+        for i in awake.iter().copied() {
+            acc[i] += settings
+                .fields
+                .iter()
+                .fold(DVec2::ZERO, |acc, f| acc + f.acceleration(positions[i], time));
+        }
+    }
+
+    if !wells.is_empty() {
+        let image = |axis| settings.bounds.min_image(axis);
+        for i in awake.iter().copied() {
+            acc[i] += wells
+                .iter()
+                .fold(DVec2::ZERO, |acc, w| acc + w.acceleration(positions[i], image));
+        }
+    }
+
+    Some(acc)
+}
+
+/// Pull of `sources` at each of `points`, summed like `PhysSettings::n_body`. The source
+/// weights can't be negative, the Barnes-Hut tree lumps them together.
+pub(super) fn pull(
+    settings: &PhysSettings,
+    force: PairForce,
+    sources: &[(DVec2, f64)],
+    points: &[DVec2],
+) -> Vec<DVec2> {
+    let image = |axis| settings.bounds.min_image(axis);
+    let mut acc = vec![DVec2::ZERO; points.len()];
+    if sources.is_empty() {
+        return acc;
+    }
+    match settings.n_body {
+        NBody::Exact => {
+            points
+                .par_iter()
+                .map(|a| {
+                    sources
+                        .iter()
+                        .map(|(pos, weight)| force.acceleration(image(*pos - *a), *weight))
+                        .reduce(|a, b| a + b)
+                        .unwrap_or_default()
+                })
+                .collect_into_vec(&mut acc);
+        }
+        NBody::BarnesHut(theta) => {
+            let tree = Quadtree::new(sources.iter().copied());
+            points
+                .par_iter()
+                .map(|pos| tree.acceleration(*pos, theta, force, image))
+                .collect_into_vec(&mut acc);
+        }
+    }
+    acc
+}
+
+/// Potential of `sources` at each of `points`, like `pull`.
+pub(super) fn potential(
+    settings: &PhysSettings,
+    force: PairForce,
+    sources: &[(DVec2, f64)],
+    points: &[DVec2],
+) -> Vec<f64> {
+    let image = |axis| settings.bounds.min_image(axis);
+    let mut potential = vec![0.0; points.len()];
+    if sources.is_empty() {
+        return potential;
+    }
+    match settings.n_body {
+        NBody::Exact => {
+            points
+                .par_iter()
+                .map(|a| {
+                    sources
+                        .iter()
+                        .map(|(pos, weight)| force.potential(image(*pos - *a), *weight))
+                        .sum::<f64>()
+                })
+                .collect_into_vec(&mut potential);
+        }
+        NBody::BarnesHut(theta) => {
+            let tree = Quadtree::new(sources.iter().copied());
+            points
+                .par_iter()
+                .map(|pos| tree.potential(*pos, theta, force, image))
+                .collect_into_vec(&mut potential);
+        }
+    }
+    potential
+}
+
+/// Positive and negative charges of `objects` at `positions`, the negative ones with their
+/// sign flipped so each can be summed on its own.
+pub(super) fn charges(
+    objects: &[PhysObject],
+    positions: &[DVec2],
+) -> (Vec<(DVec2, f64)>, Vec<(DVec2, f64)>) {
+    let (positive, negative): (Vec<_>, Vec<_>) = positions
+        .iter()
+        .zip(objects)
+        .filter(|(_, o)| o.charge != 0.0)
+        .map(|(pos, o)| (*pos, o.charge))
+        .partition(|(_, q)| *q > 0.0);
+    let negative = negative.into_iter().map(|(pos, q)| (pos, -q)).collect();
+    (positive, negative)
+}
+
+/// Electric field of `objects` at `positions` at each of `points`, the force on an object
+/// is its charge times the field.
+fn electric_field(
+    settings: &PhysSettings,
+    objects: &[PhysObject],
+    positions: &[DVec2],
+    points: &[DVec2],
+) -> Vec<DVec2> {
+    let force = settings.coulomb_force();
+    let (positive, negative) = charges(objects, positions);
+    let positive = pull(settings, force, &positive, points);
+    let negative = pull(settings, force, &negative, points);
+    positive.into_iter().zip(negative).map(|(p, n)| p - n).collect()
+}
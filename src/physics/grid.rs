@@ -0,0 +1,225 @@
+use bevy::{math::DVec2, utils::HashMap};
+
+use crate::for_pairs::Broadphase;
+
+type Cell = (i64, i64);
+
+struct GridLevel {
+    cell_size: f64,
+    cells: HashMap<Cell, Vec<usize>>,
+}
+
+impl GridLevel {
+    #[inline(always)]
+    fn cell(&self, pos: DVec2) -> Cell {
+        (
+            (pos.x / self.cell_size).floor() as i64,
+            (pos.y / self.cell_size).floor() as i64,
+        )
+    }
+
+    #[inline(always)]
+    fn for_neighbours(&self, (x, y): Cell, mut f: impl FnMut(usize)) {
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                if let Some(cell) = self.cells.get(&(x + dx, y + dy)) {
+                    cell.iter().copied().for_each(&mut f);
+                }
+            }
+        }
+    }
+}
+
+/// Hierarchical spatial hash.
+///
+/// Every element is inserted into the first level where the cells are at least as wide as its
+/// diameter, with each level being twice as coarse as the previous. Two touching elements on
+/// the same level are then always in neighbouring cells, and an element only has to look
+/// through the neighbouring cells of the coarser levels to find all bigger elements it touches.
+pub struct HGrid {
+    levels: Vec<GridLevel>,
+    entries: Vec<(usize, Cell)>,
+}
+
+impl HGrid {
+    pub fn new<E>(elements: &[E], bounds: impl Fn(&E) -> (DVec2, f64)) -> Self {
+        let min_radius = elements
+            .iter()
+            .map(|e| bounds(e).1)
+            .fold(f64::INFINITY, f64::min);
+        let base = if min_radius.is_finite() && min_radius > 0.0 {
+            min_radius * 2.0
+        } else {
+            1.0
+        };
+
+        let mut levels: Vec<GridLevel> = Vec::new();
+        let entries = elements
+            .iter()
+            .enumerate()
+            .map(|(i, e)| {
+                let (pos, radius) = bounds(e);
+                let mut level = 0;
+                while base * 2.0f64.powi(level as i32) < radius * 2.0 && level < 62 {
+                    level += 1;
+                }
+                while levels.len() <= level {
+                    levels.push(GridLevel {
+                        cell_size: base * 2.0f64.powi(levels.len() as i32),
+                        cells: HashMap::default(),
+                    });
+                }
+                let cell = levels[level].cell(pos);
+                levels[level].cells.entry(cell).or_default().push(i);
+                (level, cell)
+            })
+            .collect();
+
+        Self { levels, entries }
+    }
+
+    /// Calls `f` with every element that might overlap the circle at `pos` with `radius`.
+    pub fn for_overlapping(&self, pos: DVec2, radius: f64, mut f: impl FnMut(usize)) {
+        for level in self.levels.iter() {
+            // Elements are at most one cell wide, so their centers are within this of `pos`.
+            let reach = DVec2::splat(radius + level.cell_size / 2.0);
+            let (min, max) = (level.cell(pos - reach), level.cell(pos + reach));
+            let inside = |(x, y): Cell| x >= min.0 && x <= max.0 && y >= min.1 && y <= max.1;
+            let area = (max.0 - min.0 + 1).saturating_mul(max.1 - min.1 + 1);
+            // Big circles on fine levels are quicker to test against every occupied cell.
+            if area > level.cells.len() as i64 {
+                for (cell, elements) in level.cells.iter() {
+                    if inside(*cell) {
+                        elements.iter().copied().for_each(&mut f);
+                    }
+                }
+            } else {
+                for x in min.0..=max.0 {
+                    for y in min.1..=max.1 {
+                        if let Some(cell) = level.cells.get(&(x, y)) {
+                            cell.iter().copied().for_each(&mut f);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Only the pairs where at least one element is awake, the sleeping ones never look for
+/// neighbours themselves.
+pub struct AwakeGrid<'a> {
+    grid: &'a HGrid,
+    /// Position, radius and whether it's awake, for every element of the grid.
+    elements: Vec<(DVec2, f64, bool)>,
+}
+
+impl<'a> AwakeGrid<'a> {
+    pub fn new(grid: &'a HGrid, elements: Vec<(DVec2, f64, bool)>) -> Self {
+        Self { grid, elements }
+    }
+}
+
+impl Broadphase for AwakeGrid<'_> {
+    fn for_candidates(&self, i: usize, mut f: impl FnMut(usize)) {
+        let (pos, radius, awake) = self.elements[i];
+        if !awake {
+            return;
+        }
+        // Pairs of awake elements are found from both sides, only report them once.
+        self.grid.for_overlapping(pos, radius, |j| {
+            if j != i && (j > i || !self.elements[j].2) {
+                f(j)
+            }
+        });
+    }
+}
+
+impl Broadphase for HGrid {
+    fn for_candidates(&self, i: usize, mut f: impl FnMut(usize)) {
+        let (level, (x, y)) = self.entries[i];
+        // Pairs on the same level are found from both sides, only report them once.
+        self.levels[level].for_neighbours((x, y), |j| {
+            if j > i {
+                f(j)
+            }
+        });
+        // Cells on coarser levels are exactly 2^n times as big, so the cell can be found by
+        // shifting instead of going through the position again.
+        for (n, coarse) in self.levels[level + 1..].iter().enumerate() {
+            if !coarse.cells.is_empty() {
+                let shift = n as u32 + 1;
+                coarse.for_neighbours((x >> shift, y >> shift), &mut f);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{math::DVec2, utils::HashSet};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{AwakeGrid, HGrid};
+    use crate::for_pairs::Broadphase;
+
+    /// Radii from 1 to 4000 so most levels are used, around the origin so cells on both sides
+    /// of it have to line up when shifted.
+    fn elements() -> Vec<(DVec2, f64, bool)> {
+        let mut rng = StdRng::seed_from_u64(1);
+        (0..600)
+            .map(|_| {
+                let pos = DVec2::new(rng.gen_range(-2e4..2e4), rng.gen_range(-2e4..2e4));
+                (pos, 4000f64.powf(rng.gen_range(0.0..1.0)), rng.gen_bool(0.5))
+            })
+            .collect()
+    }
+
+    fn overlap(a: &(DVec2, f64, bool), b: &(DVec2, f64, bool)) -> bool {
+        a.0.distance(b.0) < a.1 + b.1
+    }
+
+    /// Every reported pair, panics if one is reported twice.
+    fn candidates(n: usize, broadphase: &impl Broadphase) -> HashSet<(usize, usize)> {
+        let mut pairs = HashSet::default();
+        for i in 0..n {
+            broadphase.for_candidates(i, |j| {
+                assert!(pairs.insert((i.min(j), i.max(j))), "{i} {j} reported twice");
+            });
+        }
+        pairs
+    }
+
+    #[test]
+    fn hgrid_finds_every_pair_once() {
+        let elements = elements();
+        let grid = HGrid::new(&elements, |e| (e.0, e.1));
+        let pairs = candidates(elements.len(), &grid);
+        for i in 0..elements.len() {
+            for j in i + 1..elements.len() {
+                if overlap(&elements[i], &elements[j]) {
+                    assert!(pairs.contains(&(i, j)), "{i} {j} missed");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn awake_grid_finds_every_awake_pair_once() {
+        let elements = elements();
+        let grid = HGrid::new(&elements, |e| (e.0, e.1));
+        let awake = AwakeGrid::new(&grid, elements.clone());
+        let pairs = candidates(elements.len(), &awake);
+        for i in 0..elements.len() {
+            for j in i + 1..elements.len() {
+                let (a, b) = (&elements[i], &elements[j]);
+                if overlap(a, b) && (a.2 || b.2) {
+                    assert!(pairs.contains(&(i, j)), "{i} {j} missed");
+                }
+                if !a.2 && !b.2 {
+                    assert!(!pairs.contains(&(i, j)), "{i} {j} are both asleep");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use bevy::math::DVec2;
+
+use super::{forces, well::Well, PhysObject, PhysSettings};
+
+/// Forest-Ruth coefficient, 1 / (2 - ∛2).
+const FOREST_RUTH: f64 = 1.351_207_191_959_657_6;
+
+/// How objects are moved forward in time.
+///
+/// Apart from position Verlet, velocities are taken as `(pos - pos_old) / dt` at the start of
+/// a step and stored back the same way, so the integrator can be switched at any time.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Integrator {
+    PositionVerlet,
+    VelocityVerlet,
+    /// Drift then kick, the forces are evaluated at the new position.
+    SymplecticEuler,
+    /// Fourth order symplectic, three force evaluations per step.
+    ForestRuth,
+    /// Classic fourth order Runge-Kutta, not symplectic so energy slowly drifts.
+    Rk4,
+}
+
+impl Integrator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Integrator::PositionVerlet => "Position Verlet",
+            Integrator::VelocityVerlet => "Velocity Verlet",
+            Integrator::SymplecticEuler => "Symplectic Euler",
+            Integrator::ForestRuth => "Forest-Ruth",
+            Integrator::Rk4 => "RK4",
+        }
+    }
+
+    pub fn from_str(s: &str, integrator: Integrator) -> Integrator {
+        match s {
+            "Position Verlet" => Integrator::PositionVerlet,
+            "Velocity Verlet" => Integrator::VelocityVerlet,
+            "Symplectic Euler" => Integrator::SymplecticEuler,
+            "Forest-Ruth" => Integrator::ForestRuth,
+            "RK4" => Integrator::Rk4,
+            _ => integrator,
+        }
+    }
+
+    /// Advances all awake objects by `dt`, their acceleration at the current position has
+    /// already been accumulated.
+    pub(super) fn integrate(
+        self,
+        settings: &PhysSettings,
+        wells: &[Well],
+        objects: &mut [PhysObject],
+        dt: f64,
+    ) {
+        if self == Integrator::PositionVerlet {
+            let magnetic = settings.magnetic_field.is_active();
+            objects.iter_mut().filter(|o| !o.is_asleep()).for_each(|obj| {
+                // The magnetic force only turns the velocity, doing that directly keeps the
+                // speed from creeping up.
+                if magnetic && obj.charge != 0.0 && obj.mass > 0.0 && obj.is_dynamic() {
+                    let velocity = settings.magnetic_field.rotate(
+                        obj.pos,
+                        obj.pos - obj.pos_old,
+                        settings.time,
+                        obj.charge / obj.mass,
+                        dt,
+                    );
+                    obj.pos_old = obj.pos - velocity;
+                }
+                let drag = obj.drag.unwrap_or(settings.drag);
+                obj.update_position(dt, drag);
+            });
+            return;
+        }
+
+        // Only awake dynamic objects are integrated, the rest stay where they are while the
+        // forces are sampled.
+        let moving = objects
+            .iter()
+            .map(|o| !o.is_asleep() && o.is_dynamic())
+            .collect::<Vec<_>>();
+        let mut x = objects.iter().map(|o| o.pos).collect::<Vec<_>>();
+        let mut v = objects
+            .iter()
+            .zip(&moving)
+            .map(|(o, m)| if *m { (o.pos - o.pos_old) / dt } else { DVec2::ZERO })
+            .collect::<Vec<_>>();
+        let a0 = objects.iter().map(|o| o.acceleration).collect::<Vec<_>>();
+
+        let objs = &*objects;
+        // `h` is how far into the step the state is.
+        let eval = |x: &[DVec2], v: &[DVec2], h: f64| -> Vec<DVec2> {
+            // Forces that set the velocity directly have already done so.
+            if settings.gravity_set_velocity {
+                return vec![DVec2::ZERO; x.len()];
+            }
+            let states = x.iter().copied().zip(v.iter().copied()).collect::<Vec<_>>();
+            forces::accelerations(settings, wells, objs, &states, settings.time + h)
+                .unwrap_or_else(|| vec![DVec2::ZERO; x.len()])
+        };
+        let drift = |x: &mut [DVec2], v: &[DVec2], h: f64| {
+            for i in (0..x.len()).filter(|i| moving[*i]) {
+                x[i] += v[i] * h;
+            }
+        };
+        let kick = |v: &mut [DVec2], a: &[DVec2], h: f64| {
+            for i in (0..v.len()).filter(|i| moving[*i]) {
+                v[i] += a[i] * h;
+            }
+        };
+
+        match self {
+            Integrator::PositionVerlet => unreachable!(),
+            Integrator::VelocityVerlet => {
+                kick(&mut v, &a0, dt / 2.0);
+                drift(&mut x, &v, dt);
+                let a1 = eval(&x, &v, dt);
+                kick(&mut v, &a1, dt / 2.0);
+            }
+            Integrator::SymplecticEuler => {
+                drift(&mut x, &v, dt);
+                let a1 = eval(&x, &v, dt);
+                kick(&mut v, &a1, dt);
+            }
+            Integrator::ForestRuth => {
+                let theta = FOREST_RUTH;
+                drift(&mut x, &v, theta / 2.0 * dt);
+                let a = eval(&x, &v, theta / 2.0 * dt);
+                kick(&mut v, &a, theta * dt);
+                drift(&mut x, &v, (1.0 - theta) / 2.0 * dt);
+                let a = eval(&x, &v, dt / 2.0);
+                kick(&mut v, &a, (1.0 - 2.0 * theta) * dt);
+                drift(&mut x, &v, (1.0 - theta) / 2.0 * dt);
+                let a = eval(&x, &v, (1.0 - theta / 2.0) * dt);
+                kick(&mut v, &a, theta * dt);
+                drift(&mut x, &v, theta / 2.0 * dt);
+            }
+            Integrator::Rk4 => {
+                let stage = |k_x: &[DVec2], k_v: &[DVec2], h: f64| {
+                    let (mut x, mut v) = (x.clone(), v.clone());
+                    drift(&mut x, k_x, h);
+                    kick(&mut v, k_v, h);
+                    let a = eval(&x, &v, h);
+                    (v, a)
+                };
+                let (k1_x, k1_v) = (v.clone(), a0);
+                let (k2_x, k2_v) = stage(&k1_x, &k1_v, dt / 2.0);
+                let (k3_x, k3_v) = stage(&k2_x, &k2_v, dt / 2.0);
+                let (k4_x, k4_v) = stage(&k3_x, &k3_v, dt);
+                let sum = |k1: &[DVec2], k2: &[DVec2], k3: &[DVec2], k4: &[DVec2], i: usize| {
+                    (k1[i] + 2.0 * k2[i] + 2.0 * k3[i] + k4[i]) / 6.0
+                };
+                for i in (0..x.len()).filter(|i| moving[*i]) {
+                    x[i] += sum(&k1_x, &k2_x, &k3_x, &k4_x, i) * dt;
+                    v[i] += sum(&k1_v, &k2_v, &k3_v, &k4_v, i) * dt;
+                }
+            }
+        }
+
+        for (i, obj) in objects.iter_mut().enumerate() {
+            if moving[i] {
+                // Drag is applied on its own after the step.
+                let drag = obj.drag.unwrap_or(settings.drag);
+                let v = v[i] * drag.factor(v[i].length(), dt);
+                obj.pos = x[i];
+                obj.pos_old = x[i] - v * dt;
+                obj.acceleration = DVec2::ZERO;
+                #[cfg(feature = "panic-nan")]
+                obj.panic_nan("integrate");
+            } else if !obj.is_asleep() {
+                obj.update_position(dt, settings.drag);
+            }
+        }
+    }
+}
@@ -1,44 +1,61 @@
+mod bounds;
 mod constraints;
+mod diagnostics;
+mod forces;
+mod grid;
+mod integrator;
+mod islands;
+mod nbody;
 mod object;
+mod simulation;
+mod wall;
+mod well;
 
 use std::num::NonZeroU32;
 
-use self::object::ObjectDensity;
+use self::{
+    nbody::PairForce,
+    object::{Idle, ObjectDensity},
+};
 pub use self::{
+    bounds::Bounds,
     constraints::{LinkConstraint, PointConstraint},
-    object::{Object, ObjectBundle, ObjectPos, PhysObject},
+    diagnostics::PhysDiagnostics,
+    integrator::Integrator,
+    nbody::{ForceLaw, NBody},
+    object::{BodyKind, Ccd, Charge, Drag, Material, Object, ObjectBundle, ObjectPos, PhysObject, Sleep},
+    simulation::{Contact, Merge, Simulation},
+    wall::{Segment, Wall},
+    well::{GravityWell, Well, WellMotion},
 };
 
-use crate::{for_pairs::ForPairs, physics::constraints::Constraint};
+use crate::{physics::constraints::Constraint, PlacementSettings};
 use bevy::{math::DVec2, prelude::*, utils::HashMap};
 #[cfg(feature = "math")]
 use massi::cranelift::CFunc;
-use rand::Rng;
-use rayon::{iter::ParallelIterator, slice::ParallelSliceMut};
+use rand::{rngs::StdRng, SeedableRng};
 
 #[derive(Clone)]
 pub enum Gravity {
     Dir(DVec2),
     #[cfg(feature = "math")]
     VectorField {
-        funcs: Option<(CFunc<2>, CFunc<2>)>,
+        /// Functions of `x`, `y` and the simulation time `t`.
+        funcs: Option<(CFunc<3>, CFunc<3>)>,
         x: String,
         y: String,
     },
-    None,
 }
 
 impl Gravity {
     #[inline(always)]
-    fn acceleration(&self, pos: DVec2) -> DVec2 {
+    fn acceleration(&self, pos: DVec2, time: f64) -> DVec2 {
         match self {
             Gravity::Dir(dir) => *dir,
-            // Per object is applied at a later stage
-            Gravity::None => DVec2::ZERO,
             #[cfg(feature = "math")]
             Gravity::VectorField { funcs, .. } => {
                 if let Some(funcs) = funcs {
-                    let pos = &[pos.x, pos.y];
+                    let pos = &[pos.x, pos.y, time];
                     DVec2::new(funcs.0(pos), funcs.1(pos))
                 } else {
                     DVec2::ZERO
@@ -50,7 +67,6 @@ impl Gravity {
     pub fn as_str(&self) -> &'static str {
         match self {
             Gravity::Dir(_) => "Dir",
-            Gravity::None => "None",
             #[cfg(feature = "math")]
             Gravity::VectorField { .. } => "Vector Field",
         }
@@ -65,7 +81,6 @@ impl Gravity {
                     Gravity::Dir(DVec2::new(0.0, -400.0))
                 }
             }
-            "None" => Gravity::None,
             #[cfg(feature = "math")]
             "Vector Field" => {
                 if matches!(gravity, Gravity::VectorField { .. }) {
@@ -83,304 +98,588 @@ impl Gravity {
     }
 }
 
+/// Magnetic field pointing out of the plane, it only acts on charged objects.
 #[derive(Clone)]
-pub enum Bounds {
-    Circle(f64),
-    Rect(DVec2, DVec2),
-    None,
+pub enum MagneticField {
+    Uniform(f64),
+    #[cfg(feature = "math")]
+    Expression {
+        /// Function of `x`, `y` and the simulation time `t`.
+        func: Option<CFunc<3>>,
+        expr: String,
+    },
 }
 
-impl Bounds {
-    #[allow(dead_code)]
+impl MagneticField {
     #[inline(always)]
-    fn random_point(&self, rng: &mut impl Rng, radius: f64) -> DVec2 {
+    fn strength(&self, pos: DVec2, time: f64) -> f64 {
         match self {
-            Bounds::Circle(r) => {
-                let angle = rng.gen_range(0.0..2.0 * std::f64::consts::PI);
-                let r = rng.gen_range(0.0..r - radius);
-                DVec2::new(angle.cos() * r, angle.sin() * r)
-            }
-            Bounds::Rect(min, max) => {
-                let x = rng.gen_range(min.x + radius..max.x - radius);
-                let y = rng.gen_range(min.y + radius..max.y - radius);
-                DVec2::new(x, y)
+            MagneticField::Uniform(b) => *b,
+            #[cfg(feature = "math")]
+            MagneticField::Expression { func, .. } => {
+                if let Some(func) = func {
+                    func(&[pos.x, pos.y, time])
+                } else {
+                    0.0
+                }
             }
-            Bounds::None => DVec2::new(0.0, 0.0),
         }
     }
 
-    #[inline(always)]
-    fn update_position(&self, obj: &mut PhysObject) {
+    /// Whether the field can be non-zero anywhere.
+    fn is_active(&self) -> bool {
         match self {
-            Bounds::Circle(r) => {
-                let l = obj.pos.length();
-                if l > r - obj.radius {
-                    obj.pos = obj.pos / l * (r - obj.radius);
-                }
-            }
-            Bounds::Rect(min, max) => {
-                let r = DVec2::splat(obj.radius);
-                obj.pos = obj.pos.clamp(*min + r, *max - r);
-            }
-            Bounds::None => {}
+            MagneticField::Uniform(b) => *b != 0.0,
+            #[cfg(feature = "math")]
+            MagneticField::Expression { func, .. } => func.is_some(),
         }
     }
 
+    /// Lorentz acceleration `q / m · v × B` of an object moving with `velocity` at `pos`.
+    #[inline(always)]
+    fn acceleration(&self, pos: DVec2, velocity: DVec2, time: f64, charge_per_mass: f64) -> DVec2 {
+        // With B along z, v × B is v rotated a quarter turn clockwise.
+        -velocity.perp() * (charge_per_mass * self.strength(pos, time))
+    }
+
+    /// `velocity` turned by the field over `dt`. Unlike adding `acceleration`, this keeps the
+    /// speed exactly, which position Verlet needs as it only sees last step's velocity.
+    #[inline(always)]
+    fn rotate(
+        &self,
+        pos: DVec2,
+        velocity: DVec2,
+        time: f64,
+        charge_per_mass: f64,
+        dt: f64,
+    ) -> DVec2 {
+        let angle = -charge_per_mass * self.strength(pos, time) * dt;
+        velocity * angle.cos() + velocity.perp() * angle.sin()
+    }
+
     pub fn as_str(&self) -> &'static str {
         match self {
-            Bounds::Circle(_) => "Circle",
-            Bounds::Rect(_, _) => "Rect",
-            Bounds::None => "None",
+            MagneticField::Uniform(_) => "Uniform",
+            #[cfg(feature = "math")]
+            MagneticField::Expression { .. } => "Expression",
         }
     }
 
-    pub fn from_str(s: &str, bounds: Bounds) -> Bounds {
+    pub fn from_str(s: &str, field: MagneticField) -> MagneticField {
         match s {
-            "Circle" => {
-                if matches!(bounds, Bounds::Circle(_)) {
-                    bounds
+            "Uniform" => {
+                if matches!(field, MagneticField::Uniform(_)) {
+                    field
                 } else {
-                    Bounds::Circle(200.0)
+                    MagneticField::Uniform(0.0)
                 }
             }
-            "Rect" => {
-                if matches!(bounds, Bounds::Rect(_, _)) {
-                    bounds
+            #[cfg(feature = "math")]
+            "Expression" => {
+                if matches!(field, MagneticField::Expression { .. }) {
+                    field
                 } else {
-                    Bounds::Rect(DVec2::new(-100.0, -100.0), DVec2::new(100.0, 100.0))
+                    MagneticField::Expression {
+                        func: None,
+                        expr: String::new(),
+                    }
                 }
             }
-            "None" => Bounds::None,
-            _ => bounds,
+            _ => field,
         }
     }
 }
 
-pub struct PhysSettings {
+/// One layer of force fields, the accelerations of all enabled layers are added up.
+#[derive(Clone)]
+pub struct ForceField {
+    pub enabled: bool,
     pub gravity: Gravity,
+    /// Where the field acts, `Bounds::None` is everywhere.
+    pub region: Bounds,
+}
+
+impl Default for ForceField {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            gravity: Gravity::Dir(DVec2::new(0.0, -400.0)),
+            region: Bounds::None,
+        }
+    }
+}
+
+impl ForceField {
+    #[inline(always)]
+    fn acceleration(&self, pos: DVec2, time: f64) -> DVec2 {
+        if self.enabled && self.region.contains(pos) {
+            self.gravity.acceleration(pos, time)
+        } else {
+            DVec2::ZERO
+        }
+    }
+}
+
+/// What happens when objects overlap.
+#[derive(Clone, Copy, PartialEq)]
+pub enum CollisionMode {
+    Off,
+    /// Push the objects apart.
+    Push,
+    /// Merge the objects into one, conserving mass and momentum.
+    Merge,
+}
+
+impl CollisionMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CollisionMode::Off => "Off",
+            CollisionMode::Push => "Push",
+            CollisionMode::Merge => "Merge",
+        }
+    }
+
+    pub fn from_str(s: &str, mode: CollisionMode) -> CollisionMode {
+        match s {
+            "Off" => CollisionMode::Off,
+            "Push" => CollisionMode::Push,
+            "Merge" => CollisionMode::Merge,
+            _ => mode,
+        }
+    }
+}
+
+pub struct PhysSettings {
+    /// Force field layers, applied on top of each other.
+    pub fields: Vec<ForceField>,
     pub gravity_set_velocity: bool,
     pub bounds: Bounds,
     pub gravitational_constant: f64,
+    /// Strength of the electric force between charged objects.
+    pub coulomb_constant: f64,
+    pub magnetic_field: MagneticField,
+    /// Drag for objects without their own `Drag`.
+    pub drag: Drag,
+    pub n_body: NBody,
+    /// How the pull between objects falls off with distance.
+    pub force_law: ForceLaw,
+    /// Plummer softening length for the pull between objects.
+    pub softening: f64,
+    pub integrator: Integrator,
     pub sub_steps: NonZeroU32,
-    pub collisions: bool,
+    /// Pick the number of sub steps each tick instead of using `sub_steps`.
+    pub adaptive_sub_steps: bool,
+    /// Ceiling for adaptive sub steps.
+    pub max_sub_steps: u32,
+    /// Largest movement or overlap per sub step as a fraction of the smallest radius, for
+    /// adaptive sub steps.
+    pub cfl: f64,
+    /// Simulated seconds so far, the `t` in field expressions.
+    pub time: f64,
+    /// Physics ticks per second.
+    pub tick_rate: f64,
+    /// Most ticks simulated in one frame, if we fall further behind the rest is dropped.
+    pub max_ticks: u32,
+    pub collisions: CollisionMode,
+    /// Fragments smaller than this aren't created.
+    pub min_fragment_radius: f64,
+    /// Most fragments created per step.
+    pub max_fragments: u32,
+    /// Send a `CollisionEvent` for every contact.
+    pub collision_events: bool,
+    /// Put objects that have been slow for a while to sleep.
+    pub sleeping: bool,
+    /// Objects slower than this are considered idle.
+    pub sleep_speed: f64,
+    /// How many steps linked objects have to be idle before they fall asleep.
+    pub sleep_steps: u32,
+    /// Makes results independent of query order and thread count, at some cost.
+    pub deterministic: bool,
+    /// Seed used for `SimRng` when it is reset.
+    pub seed: u64,
 }
 
 impl Default for PhysSettings {
     fn default() -> Self {
         Self {
-            gravity: Gravity::None,
+            fields: Vec::new(),
             gravity_set_velocity: false,
             bounds: Bounds::None,
             gravitational_constant: Default::default(),
+            coulomb_constant: Default::default(),
+            magnetic_field: MagneticField::Uniform(0.0),
+            drag: Drag::default(),
+            n_body: NBody::BarnesHut(0.5),
+            force_law: ForceLaw::InverseSquare,
+            softening: 0.0,
+            integrator: Integrator::PositionVerlet,
             sub_steps: NonZeroU32::new(1).unwrap(),
-            collisions: true,
+            adaptive_sub_steps: false,
+            max_sub_steps: 32,
+            cfl: 0.5,
+            time: 0.0,
+            tick_rate: 60.0,
+            max_ticks: 4,
+            collisions: CollisionMode::Push,
+            min_fragment_radius: 1.0,
+            max_fragments: 64,
+            collision_events: true,
+            sleeping: false,
+            sleep_speed: 5.0,
+            sleep_steps: 60,
+            deterministic: false,
+            seed: 0,
+        }
+    }
+}
+
+impl PhysSettings {
+    /// The gravitational pull between objects.
+    pub(super) fn n_body_force(&self) -> PairForce {
+        PairForce {
+            constant: self.gravitational_constant,
+            softening: self.softening,
+            law: self.force_law,
         }
     }
+
+    /// The electric pull between objects, it's negative so like charges repel.
+    pub(super) fn coulomb_force(&self) -> PairForce {
+        PairForce {
+            constant: -self.coulomb_constant,
+            ..self.n_body_force()
+        }
+    }
+}
+
+/// Random number generator for anything that should be reproducible given the same seed.
+pub struct SimRng(pub StdRng);
+
+impl SimRng {
+    pub fn reseed(&mut self, seed: u64) {
+        self.0 = StdRng::seed_from_u64(seed);
+    }
+}
+
+impl FromWorld for SimRng {
+    fn from_world(world: &mut World) -> Self {
+        let seed = world.get_resource::<PhysSettings>().map_or(0, |s| s.seed);
+        SimRng(StdRng::seed_from_u64(seed))
+    }
+}
+
+/// Sent for every pair of touching objects, at most once per pair and tick.
+pub struct CollisionEvent {
+    pub a: Entity,
+    pub b: Entity,
+    /// Points from `b` to `a`.
+    pub normal: DVec2,
+    pub depth: f64,
+    /// How fast the objects approached each other along the normal.
+    pub relative_speed: f64,
+}
+
+/// Frame time that has not been simulated yet.
+#[derive(Default)]
+pub struct PhysTime {
+    accumulator: f64,
+    /// How far rendering is between the last tick and the next one.
+    pub alpha: f64,
+    /// Sub steps used by the last tick.
+    pub sub_steps: u32,
 }
 
 fn physics_system(
     mut commands: Commands,
-    mut objects: Query<(Entity, (&Object, &mut ObjectPos, &ObjectDensity))>,
+    mut objects: Query<(
+        Entity,
+        (
+            &mut Object,
+            &mut ObjectPos,
+            &ObjectDensity,
+            &Material,
+            &BodyKind,
+            Option<&Drag>,
+            &mut Sleep,
+            &mut Idle,
+            Option<&Ccd>,
+            Option<&mut Charge>,
+        ),
+    )>,
     links: Query<(Entity, &LinkConstraint<Entity>)>,
     points: Query<(Entity, &PointConstraint<Entity>)>,
-    settings: Res<PhysSettings>,
+    walls: Query<(Entity, &Wall)>,
+    mut wells: Query<(Entity, &mut GravityWell)>,
+    images: Query<&Handle<Image>, With<Object>>,
+    mut settings: ResMut<PhysSettings>,
     time: Res<Time>,
+    mut phys_time: ResMut<PhysTime>,
+    mut sim: Local<Simulation>,
+    mut collision_events: EventWriter<CollisionEvent>,
+    mut diagnostics: ResMut<PhysDiagnostics>,
 ) {
     #[cfg(feature = "tracy")]
     profiling::scope!("physics system");
 
-    let (mut objs, entities) = {
+    let tick = 1.0 / settings.tick_rate;
+    phys_time.accumulator += time.delta_seconds_f64();
+    let ticks = ((phys_time.accumulator / tick) as u32).min(settings.max_ticks);
+    phys_time.accumulator = (phys_time.accumulator - ticks as f64 * tick) % tick;
+    phys_time.alpha = phys_time.accumulator / tick;
+    if ticks == 0 {
+        return;
+    }
+
+    let (entity_list, mut objs) = {
         #[cfg(feature = "tracy")]
         profiling::scope!("extract");
 
-        (
-            objects
-                .iter()
-                .map(|(_, o)| PhysObject::from(o))
-                .collect::<Vec<_>>(),
-            objects
-                .iter()
-                .enumerate()
-                .map(|(i, (e, _))| (e, i))
-                .collect::<HashMap<_, _>>(),
-        )
+        let mut extracted = objects
+            .iter()
+            .map(|(e, o)| (e, PhysObject::from(o)))
+            .collect::<Vec<_>>();
+        // Query order depends on archetypes and despawn history, entities give a stable order.
+        if settings.deterministic {
+            extracted.sort_unstable_by_key(|(e, _)| *e);
+        }
+        extracted.into_iter().unzip::<_, _, Vec<_>, Vec<_>>()
     };
+    for (key, obj) in objs.iter_mut().enumerate() {
+        obj.key = Some(key);
+    }
+    let entities = entity_list
+        .iter()
+        .enumerate()
+        .map(|(i, e)| (*e, i))
+        .collect::<HashMap<_, _>>();
+
+    let mut links = links.iter().collect::<Vec<_>>();
+    let mut points = points.iter().collect::<Vec<_>>();
+    if settings.deterministic {
+        links.sort_unstable_by_key(|(e, _)| *e);
+        points.sort_unstable_by_key(|(e, _)| *e);
+    }
 
     let links = links
-        .iter()
+        .into_iter()
         .filter_map(|(e, l)| {
-            if let Some(link) = l.try_map(|e| entities.get(e).cloned()) && link.should_stay(|i| objs[*i].pos) {
-                Some(link)
-            } else {
+            let res = l.try_map(|e| entities.get(e).cloned());
+            if res.is_none() {
                 commands.entity(e).despawn();
-                None
             }
+            res.map(|l| (e, l))
         })
         .collect::<Vec<_>>();
 
     let points = points
-        .iter()
+        .into_iter()
         .filter_map(|(e, l)| {
             let res = l.try_map(|e| entities.get(e).cloned());
             if res.is_none() {
                 commands.entity(e).despawn();
             }
-            res
+            res.map(|p| (e, p))
         })
         .collect::<Vec<_>>();
 
-    let sub_steps = u32::from(settings.sub_steps);
-    let dt = time.delta_seconds_f64() / sub_steps as f64;
-    for _ in 0..sub_steps {
-        #[cfg(feature = "tracy")]
-        profiling::scope!("tick");
-        // Handle gravity
-        {
-            #[cfg(feature = "tracy")]
-            profiling::scope!("gravity");
-
-            if settings.gravitational_constant.abs() > f64::EPSILON {
-                let constant = settings.gravitational_constant;
-
-                let objects = objs.clone();
-                objs.par_chunks_mut(8).for_each(|chunk| {
-                    if chunk.len() == 8 && false {
-
-                    } else {
-                        for a in chunk {
-                            let v = objects
-                                .iter()
-                                .map(|b| {
-                                    let axis = b.pos - a.pos;
-                                    let sqr_len = axis.length_squared();
-
-                                    if sqr_len == 0.0 {
-                                        DVec2::ZERO
-                                    } else {
-                                        axis * (b.mass * constant / sqr_len / sqr_len.sqrt())
-                                    }
-                                })
-                                .reduce(|a, b| a + b)
-                                .unwrap_or_default();
-                            if settings.gravity_set_velocity {
-                                a.set_velocity(v * dt);
-                            } else {
-                                a.accelerate(v);
-                            }
-                            #[cfg(feature = "panic-nan")]
-                            a.panic_nan("gravity")
-                        }
-                    }
-                });
-            }
-            if !matches!(settings.gravity, Gravity::None) {
-                objs.iter_mut()
-                    .for_each(|obj| {
-                    let v = settings.gravity.acceleration(obj.pos);
-                    if settings.gravity_set_velocity {
-                        obj.set_velocity(v * dt);
-                    } else {
-                        obj.accelerate(v);
-                    }
-                #[cfg(feature = "panic-nan")]
-                obj.panic_nan("const gravity");
-            });
-            }
-        }
+    let (link_entities, links) = links.into_iter().unzip::<_, _, Vec<_>, Vec<_>>();
+    let (point_entities, points) = points.into_iter().unzip::<_, _, Vec<_>, Vec<_>>();
 
-        // Handle bounds
-        if !matches!(settings.bounds, Bounds::None) {
-            #[cfg(feature = "tracy")]
-            profiling::scope!("bounds");
-            objs.iter_mut().for_each(|obj| {
-                settings.bounds.update_position(obj);
-                #[cfg(feature = "panic-nan")]
-                obj.panic_nan("bounds");
-            });
-        }
+    let mut walls = walls.iter().collect::<Vec<_>>();
+    if settings.deterministic {
+        walls.sort_unstable_by_key(|(e, _)| *e);
+    }
 
-        {
-            #[cfg(feature = "tracy")]
-            profiling::scope!("constraints");
+    sim.objects = objs;
+    sim.links = links;
+    sim.points = points;
+    sim.walls = walls.into_iter().flat_map(|(_, w)| w.segments()).collect();
 
-            for link in links.iter() {
-                link.apply(&mut objs);
+    let mut well_list = wells
+        .iter()
+        .map(|(e, well)| {
+            let follow = match well.motion {
+                WellMotion::Following(e) => entities.get(&e).copied(),
+                _ => None,
+            };
+            (e, Well::new(well, follow, settings.softening))
+        })
+        .collect::<Vec<_>>();
+    if settings.deterministic {
+        well_list.sort_unstable_by_key(|(e, _)| *e);
+    }
+    let (well_entities, well_list) = well_list.into_iter().unzip::<_, _, Vec<_>, Vec<_>>();
+    sim.wells = well_list;
+    // Lend the settings to the simulation while it runs.
+    sim.settings = std::mem::take(&mut *settings);
+    let mut merged = false;
+    // Where each object was before the last tick.
+    let mut previous = vec![None; entity_list.len()];
+    for _ in 0..ticks {
+        for obj in sim.objects.iter() {
+            if let Some(key) = obj.key {
+                previous[key] = Some(obj.pos);
             }
-
-            for point in points.iter() {
-                point.apply(&mut objs);
+        }
+        sim.step(tick);
+        let objs = &sim.objects;
+        let entity = |i: usize| objs[i].key.map(|k| entity_list[k]);
+        collision_events.send_batch(sim.contacts.iter().filter_map(|c| {
+            Some(CollisionEvent {
+                a: entity(c.a)?,
+                b: entity(c.b)?,
+                normal: c.normal,
+                depth: c.depth,
+                relative_speed: c.relative_speed,
+            })
+        }));
+        for merge in sim.merges.iter() {
+            merged = true;
+            if let (Some(into), Some(from)) = (merge.into, merge.from)
+                && let Ok((_, (from, ..))) = objects.get(entity_list[from])
+            {
+                let color = from.color;
+                if let Ok((_, (mut into, ..))) = objects.get_mut(entity_list[into]) {
+                    into.color = mix(into.color, color, merge.weight as f32);
+                }
             }
         }
-
-        // Handle collisions
-        if settings.collisions {
-            #[cfg(feature = "tracy")]
-            profiling::scope!("collisions");
-
-            objs.par_for_pairs(
-                |a, b| {
-                    let collision_axis = a.pos - b.pos;
-                    let collision_axis = if collision_axis == DVec2::ZERO {
-                        DVec2::new(f64::EPSILON, f64::EPSILON)
-                    } else {
-                        collision_axis
-                    };
-                    let combined = a.radius + b.radius;
-                    let dist_sqr = collision_axis.length_squared();
-                    if dist_sqr < combined * combined {
-                        let dist = dist_sqr.sqrt();
-                        let n = collision_axis / dist;
-                        let delta = combined - dist;
-                        Some((
-                            (b.mass / (a.mass + b.mass) * delta) * n,
-                            -(a.mass / (a.mass + b.mass) * delta) * n,
-                        ))
-                    } else {
-                        None
-                    }
-                },
-                |o, t| {
-                    o.pos += t;
-                    #[cfg(feature = "panic-nan")]
-                    o.panic_nan("collision");
-                },
-            );
+    }
+    phys_time.sub_steps = sim.sub_steps;
+    *diagnostics = PhysDiagnostics::new(&sim, tick / sim.sub_steps as f64);
+    *settings = std::mem::take(&mut sim.settings);
+    for (e, well) in well_entities.into_iter().zip(sim.wells.iter()) {
+        if let Ok((_, mut w)) = wells.get_mut(e) && w.pos != well.pos {
+            w.pos = well.pos;
         }
-
-        // Update positions
-        {
-            #[cfg(feature = "tracy")]
-            profiling::scope!("update");
-            objs.iter_mut().for_each(|obj| {
-                obj.update_position(dt);
-            });
+    }
+    // Fragments are only spawned below, so points moved onto them are dropped.
+    let shattered = sim.objects.iter().any(|o| o.key.is_none());
+
+    // Merged objects are gone, anything attached to them has been moved to what they
+    // merged into.
+    let entity = |i: &usize| sim.objects[*i].key.map(|k| entity_list[k]);
+    for (e, link) in link_entities.into_iter().zip(sim.links.iter()) {
+        if link.is_snapped() {
+            commands.entity(e).despawn();
+        } else if merged && let Some(link) = link.try_map(entity) {
+            commands.entity(e).insert(link);
+        }
+    }
+    if merged || shattered {
+        for (e, point) in point_entities.into_iter().zip(sim.points.iter()) {
+            match point.try_map(entity) {
+                Some(point) => {
+                    commands.entity(e).insert(point);
+                }
+                None => commands.entity(e).despawn(),
+            }
         }
     }
 
     {
         #[cfg(feature = "tracy")]
         profiling::scope!("insert");
-        objects
-            .iter_mut()
-            .zip(objs.into_iter())
-            .for_each(|((e, (_, mut p, _)), obj)| {
-                if obj.has_changed() {
+        let mut alive = vec![false; entity_list.len()];
+        let mut fragments = Vec::new();
+        sim.objects.drain(..).for_each(|obj| {
+            let key = match obj.key {
+                Some(key) => key,
+                None => {
+                    fragments.push(obj);
+                    return;
+                }
+            };
+            alive[key] = true;
+            let e = entity_list[key];
+            if let Ok((_, (mut o, mut p, _, _, _, _, mut sleep, mut idle, _, charge))) =
+                objects.get_mut(e)
+            {
+                // Only falling asleep and waking up change `Sleep`, which redraws the object.
+                if *sleep != obj.sleep {
+                    *sleep = obj.sleep;
+                }
+                if idle.0 != obj.idle {
+                    idle.0 = obj.idle;
+                }
+                if o.radius != obj.radius {
+                    o.radius = obj.radius;
+                }
+                // Merging can change the charge.
+                match charge {
+                    Some(mut charge) if charge.0 != obj.charge => charge.0 = obj.charge,
+                    None if obj.charge != 0.0 => {
+                        commands.entity(e).insert(Charge(obj.charge));
+                    }
+                    _ => {}
+                }
+                // Wrapping around periodic bounds shouldn't be drawn as a jump across them.
+                let prev = previous[key]
+                    .map_or(obj.pos, |prev| obj.pos - settings.bounds.min_image(obj.pos - prev));
+                if p.prev != prev {
+                    p.prev = prev;
+                }
+                if p.differs(&obj) {
                     if obj.pos.is_nan() {
                         commands.entity(e).despawn();
                     }
                     p.apply(obj);
                 }
-            })
+            }
+        });
+        // Fragments look like what they broke off from.
+        for obj in fragments {
+            let origin = match obj.origin {
+                Some(origin) => entity_list[origin],
+                None => continue,
+            };
+            if let Ok((_, (o, ..))) = objects.get(origin) && let Ok(image) = images.get(origin) {
+                let placement = PlacementSettings {
+                    radius: obj.radius,
+                    color: o.color,
+                    density: obj.mass / (std::f64::consts::PI * obj.radius * obj.radius),
+                    material: obj.material,
+                    drag: obj.drag,
+                    kind: BodyKind::Dynamic,
+                    ccd: obj.ccd,
+                    charge: obj.charge,
+                };
+                let e = ObjectBundle::spawn(&mut commands, obj.pos, &placement, image.clone());
+                commands.entity(e).insert(ObjectPos {
+                    current: obj.pos,
+                    old: obj.pos_old,
+                    prev: obj.pos,
+                });
+            }
+        }
+        for (e, alive) in entity_list.into_iter().zip(alive) {
+            if !alive {
+                commands.entity(e).despawn();
+            }
+        }
     }
 }
 
+/// Mixes two colors, `weight` is the share of `b`.
+fn mix(a: Color, b: Color, weight: f32) -> Color {
+    let (a, b) = (a.as_rgba_f32(), b.as_rgba_f32());
+    let c = |i: usize| a[i] + (b[i] - a[i]) * weight;
+    Color::rgba(c(0), c(1), c(2), c(3))
+}
+
 pub struct PhysicsPlugin;
 
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<PhysSettings>()
+            .init_resource::<PhysTime>()
+            .init_resource::<PhysDiagnostics>()
+            .init_resource::<SimRng>()
+            .add_event::<CollisionEvent>()
             .add_system(physics_system)
-            .add_system(object::update_position_system)
-            .add_system(object::update_visuals_system);
+            .add_system(object::update_position_system.after(physics_system))
+            .add_system(object::update_visuals_system)
+            .add_system(well::update_wells_system)
+            .add_system(object::update_ghosts_system.after(object::update_position_system));
     }
 }
@@ -0,0 +1,295 @@
+use bevy::math::DVec2;
+
+/// How the pairwise gravitational pull between objects is summed.
+#[derive(Clone, Copy, PartialEq)]
+pub enum NBody {
+    /// Direct summation over every pair.
+    Exact,
+    /// Barnes-Hut approximation with the given opening angle.
+    BarnesHut(f64),
+}
+
+impl NBody {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NBody::Exact => "Exact",
+            NBody::BarnesHut(_) => "Barnes-Hut",
+        }
+    }
+
+    pub fn from_str(s: &str, n_body: NBody) -> NBody {
+        match s {
+            "Exact" => NBody::Exact,
+            "Barnes-Hut" => {
+                if matches!(n_body, NBody::BarnesHut(_)) {
+                    n_body
+                } else {
+                    NBody::BarnesHut(0.5)
+                }
+            }
+            _ => n_body,
+        }
+    }
+}
+
+/// How the pull between two objects falls off with distance.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ForceLaw {
+    /// Newtonian, proportional to 1 / r².
+    InverseSquare,
+    /// Proportional to 1 / rⁿ with the given exponent.
+    Power(f64),
+    /// Screened inverse-square, `e^(-r / range) / r` potential with the given range.
+    Yukawa(f64),
+}
+
+impl ForceLaw {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ForceLaw::InverseSquare => "Inverse Square",
+            ForceLaw::Power(_) => "Power",
+            ForceLaw::Yukawa(_) => "Yukawa",
+        }
+    }
+
+    pub fn from_str(s: &str, law: ForceLaw) -> ForceLaw {
+        match s {
+            "Inverse Square" => ForceLaw::InverseSquare,
+            "Power" => {
+                if matches!(law, ForceLaw::Power(_)) {
+                    law
+                } else {
+                    ForceLaw::Power(2.0)
+                }
+            }
+            "Yukawa" => {
+                if matches!(law, ForceLaw::Yukawa(_)) {
+                    law
+                } else {
+                    ForceLaw::Yukawa(100.0)
+                }
+            }
+            _ => law,
+        }
+    }
+}
+
+/// Pairwise attraction between objects.
+#[derive(Clone, Copy)]
+pub struct PairForce {
+    pub constant: f64,
+    /// Plummer softening length, distances are taken as `sqrt(r² + ε²)` so close passes
+    /// stay finite.
+    pub softening: f64,
+    pub law: ForceLaw,
+}
+
+impl PairForce {
+    /// Acceleration towards a mass at `axis` relative to the accelerated object.
+    #[inline(always)]
+    pub fn acceleration(&self, axis: DVec2, mass: f64) -> DVec2 {
+        let sqr_len = axis.length_squared() + self.softening * self.softening;
+
+        if sqr_len == 0.0 {
+            return DVec2::ZERO;
+        }
+        let len = sqr_len.sqrt();
+        // Magnitude of the pull divided by the distance.
+        let pull = match self.law {
+            ForceLaw::InverseSquare => 1.0 / sqr_len / len,
+            ForceLaw::Power(n) => len.powf(-n - 1.0),
+            ForceLaw::Yukawa(range) => (-len / range).exp() * (1.0 / len + 1.0 / range) / sqr_len,
+        };
+        axis * (mass * self.constant * pull)
+    }
+
+    /// Potential of a mass at `axis` relative to a point.
+    #[inline(always)]
+    pub fn potential(&self, axis: DVec2, mass: f64) -> f64 {
+        let len = (axis.length_squared() + self.softening * self.softening).sqrt();
+
+        if len == 0.0 {
+            return 0.0;
+        }
+        -mass
+            * self.constant
+            * match self.law {
+                ForceLaw::InverseSquare => 1.0 / len,
+                ForceLaw::Power(n) if (n - 1.0).abs() < f64::EPSILON => -len.ln(),
+                ForceLaw::Power(n) => len.powf(1.0 - n) / (n - 1.0),
+                ForceLaw::Yukawa(range) => (-len / range).exp() / len,
+            }
+    }
+}
+
+/// Subdividing further than this can't separate bodies anyway, they are merged instead.
+const MAX_DEPTH: u32 = 48;
+
+struct Node {
+    center: DVec2,
+    half: f64,
+    mass: f64,
+    center_of_mass: DVec2,
+    count: u32,
+    /// Index of the first of four consecutive children.
+    children: Option<usize>,
+}
+
+impl Node {
+    fn new(center: DVec2, half: f64) -> Self {
+        Self {
+            center,
+            half,
+            mass: 0.0,
+            center_of_mass: DVec2::ZERO,
+            count: 0,
+            children: None,
+        }
+    }
+
+    #[inline(always)]
+    fn add(&mut self, pos: DVec2, mass: f64) {
+        let total = self.mass + mass;
+        self.center_of_mass = if total == 0.0 {
+            pos
+        } else {
+            (self.center_of_mass * self.mass + pos * mass) / total
+        };
+        self.mass = total;
+        self.count += 1;
+    }
+
+    #[inline(always)]
+    fn quadrant(&self, pos: DVec2) -> usize {
+        (pos.x >= self.center.x) as usize | ((pos.y >= self.center.y) as usize) << 1
+    }
+}
+
+/// Quadtree of masses used for Barnes-Hut gravity.
+pub struct Quadtree {
+    nodes: Vec<Node>,
+}
+
+impl Quadtree {
+    pub fn new(bodies: impl Iterator<Item = (DVec2, f64)> + Clone) -> Self {
+        let (min, max) = bodies.clone().fold(
+            (DVec2::splat(f64::INFINITY), DVec2::splat(f64::NEG_INFINITY)),
+            |(min, max), (pos, _)| (min.min(pos), max.max(pos)),
+        );
+        let (center, half) = if min.x <= max.x {
+            ((min + max) / 2.0, (max - min).max_element() / 2.0 + 1.0)
+        } else {
+            (DVec2::ZERO, 1.0)
+        };
+
+        let mut tree = Self {
+            nodes: vec![Node::new(center, half)],
+        };
+        for (pos, mass) in bodies {
+            tree.insert(pos, mass);
+        }
+        tree
+    }
+
+    fn insert(&mut self, pos: DVec2, mass: f64) {
+        let mut node = 0;
+        let mut depth = 0;
+        loop {
+            if let Some(first) = self.nodes[node].children {
+                self.nodes[node].add(pos, mass);
+                node = first + self.nodes[node].quadrant(pos);
+                depth += 1;
+            } else if self.nodes[node].count == 0 || depth >= MAX_DEPTH {
+                self.nodes[node].add(pos, mass);
+                return;
+            } else {
+                // Split the leaf and push the body it holds down one level.
+                let first = self.nodes.len();
+                let Node {
+                    center,
+                    half,
+                    mass: old_mass,
+                    center_of_mass: old_pos,
+                    count,
+                    ..
+                } = self.nodes[node];
+                let half = half / 2.0;
+                for i in 0..4 {
+                    let offset = DVec2::new(
+                        if i & 1 == 0 { -half } else { half },
+                        if i & 2 == 0 { -half } else { half },
+                    );
+                    self.nodes.push(Node::new(center + offset, half));
+                }
+                let quadrant = self.nodes[node].quadrant(old_pos);
+                let child = &mut self.nodes[first + quadrant];
+                child.mass = old_mass;
+                child.center_of_mass = old_pos;
+                child.count = count;
+                self.nodes[node].children = Some(first);
+            }
+        }
+    }
+
+    /// Visits the nodes that are far enough away from `pos` to be treated as a single mass,
+    /// and the leaves that aren't.
+    fn for_nodes(
+        &self,
+        pos: DVec2,
+        theta: f64,
+        image: impl Fn(DVec2) -> DVec2,
+        mut f: impl FnMut(DVec2, f64),
+    ) {
+        let mut stack = vec![0];
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            if node.count == 0 {
+                continue;
+            }
+            let axis = image(node.center_of_mass - pos);
+            // Nodes around `pos` are always opened, with a large `theta` their center of mass
+            // can be far enough away to pass, and `pos` would pull on itself.
+            let around = image(pos - node.center).abs().max_element() <= node.half;
+            match node.children {
+                Some(first)
+                    if around
+                        || (node.half * 2.0).powi(2) >= theta * theta * axis.length_squared() =>
+                {
+                    stack.extend(first..first + 4);
+                }
+                _ => f(axis, node.mass),
+            }
+        }
+    }
+
+    /// Approximates the acceleration at `pos`, nodes that appear smaller than `theta` are
+    /// treated as a single mass. `image` maps offsets to their minimum image.
+    pub fn acceleration(
+        &self,
+        pos: DVec2,
+        theta: f64,
+        force: PairForce,
+        image: impl Fn(DVec2) -> DVec2,
+    ) -> DVec2 {
+        let mut acc = DVec2::ZERO;
+        self.for_nodes(pos, theta, image, |axis, mass| {
+            acc += force.acceleration(axis, mass)
+        });
+        acc
+    }
+
+    /// Approximates the gravitational potential at `pos` like `acceleration`.
+    pub fn potential(
+        &self,
+        pos: DVec2,
+        theta: f64,
+        force: PairForce,
+        image: impl Fn(DVec2) -> DVec2,
+    ) -> f64 {
+        let mut potential = 0.0;
+        self.for_nodes(pos, theta, image, |axis, mass| {
+            potential += force.potential(axis, mass)
+        });
+        potential
+    }
+}
@@ -2,6 +2,8 @@ use bevy::{math::DVec2, prelude::*};
 
 use crate::PlacementSettings;
 
+use super::{mix, Bounds, PhysSettings, PhysTime};
+
 #[derive(Component)]
 pub struct ObjectDensity(f64);
 
@@ -9,9 +11,19 @@ pub struct ObjectDensity(f64);
 pub struct ObjectPos {
     pub current: DVec2,
     pub old: DVec2,
+    /// Where the object was at the end of the tick before the last one, rendering
+    /// interpolates from here to `current`.
+    pub prev: DVec2,
 }
 
 impl ObjectPos {
+    /// Whether `obj` ended up anywhere else than what's stored, a body can be moved without
+    /// being left with any velocity.
+    #[inline(always)]
+    pub fn differs(&self, obj: &PhysObject) -> bool {
+        self.current != obj.pos || self.old != obj.pos_old
+    }
+
     pub fn apply(&mut self, obj: PhysObject) {
         self.current = obj.pos;
         self.old = obj.pos_old;
@@ -24,11 +36,152 @@ pub struct Object {
     pub radius: f64,
 }
 
+/// How an object responds to contacts.
+#[derive(Component, Clone, Copy)]
+pub struct Material {
+    /// Coefficient of restitution, 0 is perfectly inelastic and 1 perfectly elastic.
+    pub restitution: f64,
+    /// Coulomb friction coefficient.
+    pub friction: f64,
+    /// Impact speed above which the object shatters.
+    pub strength: f64,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            restitution: 0.0,
+            friction: 0.0,
+            strength: f64::INFINITY,
+        }
+    }
+}
+
+impl Material {
+    /// The material used for a contact between two objects.
+    #[inline(always)]
+    pub fn combine(self, other: Material) -> Material {
+        Material {
+            restitution: self.restitution.max(other.restitution),
+            friction: (self.friction * other.friction).sqrt(),
+            strength: self.strength.min(other.strength),
+        }
+    }
+
+    /// Change of relative velocity `rel` from a contact with normal `n`, on top of what the
+    /// positional correction already does.
+    #[inline(always)]
+    pub fn response(self, rel: DVec2, n: DVec2) -> DVec2 {
+        let vn = rel.dot(n);
+        if vn >= 0.0 {
+            return DVec2::ZERO;
+        }
+        let tangent = rel - n * vn;
+        let tangent_len = tangent.length();
+        let friction = if tangent_len > 0.0 {
+            let max = self.friction * (1.0 + self.restitution) * -vn;
+            -tangent * (max.min(tangent_len) / tangent_len)
+        } else {
+            DVec2::ZERO
+        };
+        n * (-self.restitution * vn) + friction
+    }
+}
+
+/// Velocity damping, applied as a deceleration of `linear * v + quadratic * |v| * v`.
+#[derive(Component, Clone, Copy, Default)]
+pub struct Drag {
+    pub linear: f64,
+    pub quadratic: f64,
+}
+
+impl Drag {
+    /// How much of the velocity is left after `dt` seconds.
+    #[inline(always)]
+    pub(super) fn factor(self, speed: f64, dt: f64) -> f64 {
+        (1.0 - (self.linear + self.quadratic * speed) * dt).max(0.0)
+    }
+}
+
+/// How an object is moved by the simulation.
+#[derive(Component, Clone, Copy, PartialEq)]
+pub enum BodyKind {
+    /// Moved by forces, contacts and links.
+    Dynamic,
+    /// Never moves and has infinite mass.
+    Static,
+    /// Moves with a fixed velocity and has infinite mass.
+    Kinematic(DVec2),
+}
+
+impl Default for BodyKind {
+    fn default() -> Self {
+        BodyKind::Dynamic
+    }
+}
+
+impl BodyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BodyKind::Dynamic => "Dynamic",
+            BodyKind::Static => "Static",
+            BodyKind::Kinematic(_) => "Kinematic",
+        }
+    }
+
+    pub fn from_str(s: &str, kind: BodyKind) -> BodyKind {
+        match s {
+            "Dynamic" => BodyKind::Dynamic,
+            "Static" => BodyKind::Static,
+            "Kinematic" => {
+                if matches!(kind, BodyKind::Kinematic(_)) {
+                    kind
+                } else {
+                    BodyKind::Kinematic(DVec2::new(100.0, 0.0))
+                }
+            }
+            _ => kind,
+        }
+    }
+}
+
+/// Opts an object into continuous collision detection, so it can't tunnel through others.
+#[derive(Component, Clone, Copy)]
+pub struct Ccd;
+
+/// Electric charge, like charges repel and opposite ones attract.
+#[derive(Component, Clone, Copy, Default, PartialEq)]
+pub struct Charge(pub f64);
+
+/// Charge at which an object is tinted halfway to red or blue.
+const TINT_CHARGE: f64 = 10.0;
+
+/// Objects that have been slow for long enough are put to sleep and skipped by the simulation.
+#[derive(Component, Clone, Copy, Default, PartialEq)]
+pub struct Sleep {
+    asleep: bool,
+}
+
+impl Sleep {
+    pub fn is_asleep(&self) -> bool {
+        self.asleep
+    }
+}
+
+/// Steps an object has been slow for. It's kept apart from `Sleep`, which only changes when
+/// the object falls asleep or wakes up.
+#[derive(Component, Clone, Copy, Default)]
+pub struct Idle(pub(super) u32);
+
 #[derive(Bundle)]
 pub struct ObjectBundle {
     object: Object,
     pos: ObjectPos,
     density: ObjectDensity,
+    material: Material,
+    kind: BodyKind,
+    sleep: Sleep,
+    idle: Idle,
     sprite: Sprite,
     transform: Transform,
     global_transform: GlobalTransform,
@@ -44,8 +197,13 @@ impl ObjectBundle {
             pos: ObjectPos {
                 current: pos,
                 old: pos,
+                prev: pos,
             },
             density: ObjectDensity(settings.density),
+            material: settings.material,
+            kind: settings.kind,
+            sleep: Sleep::default(),
+            idle: Idle::default(),
             sprite: Sprite {
                 color: settings.color,
                 custom_size: Some(Vec2::ONE),
@@ -61,6 +219,26 @@ impl ObjectBundle {
             visibility: Default::default(),
         }
     }
+
+    /// Spawns the object together with the optional components from `settings`.
+    pub fn spawn(
+        commands: &mut Commands,
+        pos: DVec2,
+        settings: &PlacementSettings,
+        image: Handle<Image>,
+    ) -> Entity {
+        let mut entity = commands.spawn_bundle(Self::new(pos, settings, image));
+        if let Some(drag) = settings.drag {
+            entity.insert(drag);
+        }
+        if settings.ccd {
+            entity.insert(Ccd);
+        }
+        if settings.charge != 0.0 {
+            entity.insert(Charge(settings.charge));
+        }
+        entity.id()
+    }
 }
 
 #[derive(Clone)]
@@ -70,39 +248,218 @@ pub struct PhysObject {
     pub(super) acceleration: DVec2,
     pub(super) radius: f64,
     pub(super) mass: f64,
+    pub(super) charge: f64,
+    pub(super) material: Material,
+    pub(super) kind: BodyKind,
+    /// Overrides `PhysSettings::drag`.
+    pub(super) drag: Option<Drag>,
+    pub(super) sleep: Sleep,
+    /// Steps the object has been slow for.
+    pub(super) idle: u32,
+    pub(super) ccd: bool,
+    /// Identifies the object this was extracted from, objects created by the simulation have
+    /// none.
+    pub(super) key: Option<usize>,
+    /// Key of the object this is a fragment of.
+    pub(super) origin: Option<usize>,
 }
 
-impl From<(&Object, &ObjectPos, &ObjectDensity)> for PhysObject {
-    fn from((obj, pos, density): (&Object, &ObjectPos, &ObjectDensity)) -> Self {
+/// The components a `PhysObject` is extracted from.
+pub type ObjectComponents<'a> = (
+    &'a Object,
+    &'a ObjectPos,
+    &'a ObjectDensity,
+    &'a Material,
+    &'a BodyKind,
+    Option<&'a Drag>,
+    &'a Sleep,
+    &'a Idle,
+    Option<&'a Ccd>,
+    Option<&'a Charge>,
+);
+
+impl From<ObjectComponents<'_>> for PhysObject {
+    fn from(
+        (obj, pos, density, material, kind, drag, sleep, idle, ccd, charge): ObjectComponents,
+    ) -> Self {
         PhysObject {
-            pos: pos.current,
-            pos_old: pos.old,
-            acceleration: DVec2::ZERO,
-            radius: obj.radius,
-            mass: obj.radius * obj.radius * density.0 * std::f64::consts::PI,
+            // Sleeping objects are at rest, whatever was stored before they fell asleep.
+            pos_old: if sleep.asleep { pos.current } else { pos.old },
+            charge: charge.map_or(0.0, |c| c.0),
+            material: *material,
+            kind: *kind,
+            drag: drag.copied(),
+            sleep: *sleep,
+            idle: idle.0,
+            ccd: ccd.is_some(),
+            ..PhysObject::new(pos.current, obj.radius, density.0)
         }
     }
 }
 
 impl PhysObject {
+    /// A resting object at `pos`.
+    pub fn new(pos: DVec2, radius: f64, density: f64) -> Self {
+        PhysObject {
+            pos,
+            pos_old: pos,
+            acceleration: DVec2::ZERO,
+            radius,
+            mass: radius * radius * density * std::f64::consts::PI,
+            charge: 0.0,
+            material: Material::default(),
+            kind: BodyKind::Dynamic,
+            drag: None,
+            sleep: Sleep::default(),
+            idle: 0,
+            ccd: false,
+            key: None,
+            origin: None,
+        }
+    }
+
+    /// Starts the object moving with `velocity`, `dt` is the length of the sub steps it will
+    /// take.
+    pub fn with_velocity(mut self, velocity: DVec2, dt: f64) -> Self {
+        self.pos_old = self.pos - velocity * dt;
+        self
+    }
+
+    pub fn with_charge(mut self, charge: f64) -> Self {
+        self.charge = charge;
+        self
+    }
+
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = material;
+        self
+    }
+
+    pub fn with_kind(mut self, kind: BodyKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    #[inline(always)]
+    pub fn pos(&self) -> DVec2 {
+        self.pos
+    }
+
+    /// Velocity over the last sub step, which was `dt` long.
+    #[inline(always)]
+    pub fn velocity(&self, dt: f64) -> DVec2 {
+        (self.pos - self.pos_old) / dt
+    }
+
+    #[inline(always)]
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    #[inline(always)]
+    pub fn mass(&self) -> f64 {
+        self.mass
+    }
+
+    #[inline(always)]
+    pub fn charge(&self) -> f64 {
+        self.charge
+    }
+
+    #[inline(always)]
+    pub fn kind(&self) -> BodyKind {
+        self.kind
+    }
+
+    #[inline(always)]
+    pub fn is_asleep(&self) -> bool {
+        self.sleep.asleep
+    }
+
+    #[inline(always)]
+    pub fn is_dynamic(&self) -> bool {
+        self.kind == BodyKind::Dynamic
+    }
+
+    /// Sleeping, static and kinematic objects can't be moved by other objects.
+    #[inline(always)]
+    pub fn inv_mass(&self) -> f64 {
+        if self.sleep.asleep || !self.is_dynamic() {
+            0.0
+        } else {
+            1.0 / self.mass
+        }
+    }
+
     #[inline(always)]
-    pub fn has_changed(&self) -> bool {
-        self.pos != self.pos_old
+    pub fn wake(&mut self) {
+        self.sleep = Sleep::default();
+        self.idle = 0;
     }
 
+    /// Stops the object where it is, `pos_old` is moved onto `pos` so it has no velocity left.
     #[inline(always)]
-    pub fn update_position(&mut self, dt: f64) {
+    pub fn fall_asleep(&mut self) {
+        self.sleep.asleep = true;
+        self.pos_old = self.pos;
+        self.acceleration = DVec2::ZERO;
+    }
+
+    /// Moves the object back along its last step, keeping its velocity.
+    #[inline(always)]
+    pub fn rewind(&mut self, t: f64) {
+        let velocity = self.pos - self.pos_old;
+        self.pos = self.pos_old + velocity * t;
+        self.pos_old = self.pos - velocity;
+    }
+
+    /// Counts how long the object has been slower than `speed`.
+    #[inline(always)]
+    pub fn update_idle(&mut self, speed: f64, dt: f64) {
+        if (self.pos - self.pos_old).length_squared() < (speed * dt).powi(2) {
+            self.idle = self.idle.saturating_add(1);
+        } else {
+            self.idle = 0;
+        }
+    }
+
+    /// Kinematic objects are never idle, they would stop moving once asleep.
+    #[inline(always)]
+    pub fn is_idle(&self, steps: u32) -> bool {
+        !matches!(self.kind, BodyKind::Kinematic(_)) && (self.sleep.asleep || self.idle >= steps)
+    }
+
+    /// Applies restitution and friction against something immovable, `normal` points away
+    /// from it and `velocity` is what the object had before it was pushed out.
+    #[inline(always)]
+    pub fn bounce(&mut self, normal: DVec2, velocity: DVec2) {
+        let vn = velocity.dot(normal);
+        if vn < 0.0 {
+            let velocity = velocity - normal * vn + self.material.response(velocity, normal);
+            self.pos_old = self.pos - velocity;
+        }
+    }
+
+    #[inline(always)]
+    pub fn update_position(&mut self, dt: f64, drag: Drag) {
         #[cfg(feature = "panic-nan")]
         self.panic_nan("pre update");
-        let velocity = self.pos - self.pos_old;
+        let velocity = match self.kind {
+            BodyKind::Dynamic => {
+                let velocity = self.pos - self.pos_old;
+                velocity * drag.factor(velocity.length() / dt, dt)
+                    + self.acceleration * dt * dt
+            }
+            BodyKind::Static => DVec2::ZERO,
+            BodyKind::Kinematic(velocity) => velocity * dt,
+        };
         self.pos_old = self.pos;
-        self.pos += velocity + self.acceleration * dt * dt;
+        self.pos += velocity;
         self.acceleration = DVec2::ZERO;
         #[cfg(feature = "panic-nan")]
         self.panic_nan("post update");
     }
 
-    
     #[inline(always)]
     pub fn set_velocity(&mut self, vel: DVec2) {
         self.pos_old = self.pos - vel;
@@ -128,26 +485,106 @@ impl PhysObject {
 }
 
 pub(super) fn update_position_system(
-    mut positions: Query<(&ObjectPos, &mut Transform), Changed<ObjectPos>>,
+    mut positions: Query<(&ObjectPos, &mut Transform)>,
+    phys_time: Res<PhysTime>,
 ) {
     #[cfg(feature = "tracy")]
     profiling::scope!("update position system");
     positions.for_each_mut(|(pos, mut transform)| {
-        transform.translation = Vec3::new(
-            pos.current.x as f32,
-            pos.current.y as f32,
-            transform.translation.z,
-        );
+        let p = pos.prev.lerp(pos.current, phys_time.alpha);
+        transform.translation = Vec3::new(p.x as f32, p.y as f32, transform.translation.z);
     });
 }
 
 pub(super) fn update_visuals_system(
-    mut objects: Query<(&Object, &mut Transform, &mut Sprite), Changed<Object>>,
+    mut objects: Query<
+        (&Object, &Sleep, Option<&Charge>, &mut Transform, &mut Sprite),
+        Or<(Changed<Object>, Changed<Sleep>, Changed<Charge>)>,
+    >,
 ) {
     #[cfg(feature = "tracy")]
     profiling::scope!("update visuals system");
-    objects.for_each_mut(|(obj, mut transform, mut sprite)| {
-        sprite.color = obj.color;
+    objects.for_each_mut(|(obj, sleep, charge, mut transform, mut sprite)| {
+        // Positive charges are tinted red and negative ones blue.
+        let color = match charge {
+            Some(Charge(q)) if *q != 0.0 => {
+                let tint = if *q > 0.0 { Color::RED } else { Color::BLUE };
+                mix(obj.color, tint, (q.abs() / (q.abs() + TINT_CHARGE)) as f32)
+            }
+            _ => obj.color,
+        };
+        sprite.color = if sleep.is_asleep() {
+            let c = color;
+            Color::rgba(c.r() * 0.5, c.g() * 0.5, c.b() * 0.5, c.a())
+        } else {
+            color
+        };
         transform.scale = Vec3::splat(obj.radius as f32 * 2.0);
     });
 }
+
+/// Sprite drawing an object a second time on the other side of periodic bounds.
+#[derive(Component)]
+pub(super) struct Ghost;
+
+pub(super) fn update_ghosts_system(
+    mut commands: Commands,
+    objects: Query<(&Transform, &Sprite, &Handle<Image>), (With<Object>, Without<Ghost>)>,
+    mut ghosts: Query<
+        (&mut Transform, &mut Sprite, &mut Handle<Image>, &mut Visibility),
+        With<Ghost>,
+    >,
+    settings: Res<PhysSettings>,
+    mut pool: Local<Vec<Entity>>,
+) {
+    #[cfg(feature = "tracy")]
+    profiling::scope!("update ghosts system");
+    let mut used = 0;
+    if let Bounds::Periodic(min, max) = settings.bounds {
+        let (min, max) = (min.as_vec2(), max.as_vec2());
+        let size = max - min;
+        for (transform, sprite, image) in objects.iter() {
+            let pos = transform.translation.truncate();
+            let radius = transform.scale.x / 2.0;
+            for x in [-size.x, 0.0, size.x] {
+                for y in [-size.y, 0.0, size.y] {
+                    let p = pos + Vec2::new(x, y);
+                    if x == 0.0 && y == 0.0
+                        || (p + radius).cmplt(min).any()
+                        || (p - radius).cmpgt(max).any()
+                    {
+                        continue;
+                    }
+                    let translation = p.extend(transform.translation.z);
+                    let transform = Transform { translation, ..*transform };
+                    if let Some(e) = pool.get(used) {
+                        if let Ok((mut t, mut s, mut i, mut visibility)) = ghosts.get_mut(*e) {
+                            *t = transform;
+                            s.color = sprite.color;
+                            *i = image.clone();
+                            visibility.is_visible = true;
+                        }
+                    } else {
+                        // Ghosts spawned now show up next frame.
+                        let e = commands
+                            .spawn_bundle(SpriteBundle {
+                                sprite: sprite.clone(),
+                                transform,
+                                texture: image.clone(),
+                                ..default()
+                            })
+                            .insert(Ghost)
+                            .id();
+                        pool.push(e);
+                    }
+                    used += 1;
+                }
+            }
+        }
+    }
+    for e in &pool[used..] {
+        if let Ok((_, _, _, mut visibility)) = ghosts.get_mut(*e) {
+            visibility.is_visible = false;
+        }
+    }
+}
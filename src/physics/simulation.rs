@@ -0,0 +1,806 @@
+use bevy::math::DVec2;
+use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
+
+use super::{
+    constraints::Constraint,
+    forces,
+    grid::{AwakeGrid, HGrid},
+    islands::UnionFind,
+    wall::Segment,
+    well::Well,
+    Bounds, CollisionMode, LinkConstraint, Material, PhysObject, PhysSettings, PointConstraint,
+    Sleep,
+};
+use crate::for_pairs::{sort_pairs, ForPairs};
+
+/// A contact between two objects.
+#[derive(Clone, Copy)]
+pub struct Contact {
+    pub a: usize,
+    pub b: usize,
+    /// Points from `b` to `a`.
+    pub normal: DVec2,
+    pub depth: f64,
+    /// How fast the objects approached each other along the normal.
+    pub relative_speed: f64,
+}
+
+/// An object that was merged into another one.
+#[derive(Clone, Copy)]
+pub struct Merge {
+    /// Key of the object that is left.
+    pub into: Option<usize>,
+    /// Key of the object that is gone.
+    pub from: Option<usize>,
+    /// Share of the merged mass that came from `from`.
+    pub weight: f64,
+}
+
+/// The physics simulation on its own, without any ECS.
+///
+/// Constraints refer to objects by their index in `objects`.
+#[derive(Default)]
+pub struct Simulation {
+    pub settings: PhysSettings,
+    pub objects: Vec<PhysObject>,
+    pub links: Vec<LinkConstraint<usize>>,
+    pub points: Vec<PointConstraint<usize>>,
+    pub walls: Vec<Segment>,
+    pub wells: Vec<Well>,
+    /// Contacts from the last step if `settings.collision_events` is set, only the deepest
+    /// contact of each pair is kept.
+    pub contacts: Vec<Contact>,
+    /// Merges during the last step, in the order they happened.
+    pub merges: Vec<Merge>,
+    /// How many more fragments may be created during this step, objects don't shatter once
+    /// this runs out.
+    fragment_budget: u32,
+    /// Sub steps used by the last step.
+    pub sub_steps: u32,
+    /// Length of the last sub step, velocities are stored relative to it.
+    sub_dt: f64,
+    /// Deepest overlap between objects during the last step.
+    max_penetration: f64,
+}
+
+impl Simulation {
+    /// An empty simulation, objects and constraints are added to its fields directly.
+    pub fn new(settings: PhysSettings) -> Self {
+        Self {
+            settings,
+            ..Default::default()
+        }
+    }
+
+    /// Length of the last sub step.
+    pub fn sub_dt(&self) -> f64 {
+        self.sub_dt
+    }
+
+    /// Advances the simulation by `dt` seconds, split into `settings.sub_steps` steps.
+    pub fn step(&mut self, dt: f64) {
+        #[cfg(feature = "tracy")]
+        profiling::scope!("step");
+
+        for link in self.links.iter_mut() {
+            let image = |axis| self.settings.bounds.min_image(axis);
+            if !link.is_snapped() && !link.should_stay(|i| self.objects[*i].pos, image) {
+                link.snap();
+                let (a, b) = link.ends();
+                self.objects[*a].wake();
+                self.objects[*b].wake();
+            }
+        }
+
+        if !self.settings.sleeping {
+            self.objects
+                .iter_mut()
+                .filter(|o| o.is_asleep())
+                .for_each(|o| o.wake());
+        }
+
+        self.contacts.clear();
+        self.merges.clear();
+        self.fragment_budget = self.settings.max_fragments;
+
+        let sub_steps = if self.settings.adaptive_sub_steps {
+            self.adaptive_sub_steps(dt)
+        } else {
+            u32::from(self.settings.sub_steps)
+        };
+        let dt = dt / sub_steps as f64;
+        // Velocities are implied by the last step, so they have to be scaled to the new one.
+        if self.sub_dt > 0.0 && self.sub_dt != dt {
+            let scale = dt / self.sub_dt;
+            for obj in self.objects.iter_mut() {
+                obj.pos_old = obj.pos - (obj.pos - obj.pos_old) * scale;
+            }
+        }
+        self.sub_dt = dt;
+        self.sub_steps = sub_steps;
+        self.max_penetration = 0.0;
+        for well in self.wells.iter_mut() {
+            well.find_followed(&self.objects);
+        }
+        // Walls don't move, the same grid is used for every sub step.
+        let wall_grid = (!self.walls.is_empty())
+            .then(|| HGrid::new(self.walls.as_slice(), Segment::bounds));
+        for _ in 0..sub_steps {
+            self.sub_step(dt, wall_grid.as_ref());
+            self.settings.time += dt;
+        }
+
+        self.contacts.sort_unstable_by(|c0, c1| {
+            (c0.a, c0.b)
+                .cmp(&(c1.a, c1.b))
+                .then(c1.depth.total_cmp(&c0.depth))
+        });
+        self.contacts.dedup_by_key(|c| (c.a, c.b));
+    }
+
+    /// Enough sub steps for the fastest object to move at most `settings.cfl` times the
+    /// smallest radius per sub step, and for overlaps to stay below that as well.
+    fn adaptive_sub_steps(&self, dt: f64) -> u32 {
+        let min_radius = self
+            .objects
+            .iter()
+            .fold(f64::INFINITY, |r, o| o.radius.min(r));
+        if !min_radius.is_finite() || min_radius <= 0.0 {
+            return 1;
+        }
+        let limit = self.settings.cfl * min_radius;
+        let max_speed = if self.sub_dt > 0.0 {
+            self.objects
+                .iter()
+                .filter(|o| !o.is_asleep())
+                .fold(0.0, |v, o| (o.pos - o.pos_old).length().max(v))
+                / self.sub_dt
+        } else {
+            0.0
+        };
+        let by_speed = max_speed * dt / limit;
+        // Overlaps shrink roughly in proportion to the sub step length.
+        let by_penetration = self.sub_steps as f64 * self.max_penetration / limit;
+        (by_speed.max(by_penetration).ceil() as u32).clamp(1, self.settings.max_sub_steps.max(1))
+    }
+
+    /// `wall_grid` holds `walls`, if there are any.
+    fn sub_step(&mut self, dt: f64, wall_grid: Option<&HGrid>) {
+        #[cfg(feature = "tracy")]
+        profiling::scope!("tick");
+
+        let Simulation {
+            settings,
+            objects,
+            links,
+            points,
+            walls,
+            wells,
+            contacts,
+            merges,
+            fragment_budget,
+            max_penetration,
+            ..
+        } = self;
+
+        // Handle gravity
+        {
+            #[cfg(feature = "tracy")]
+            profiling::scope!("gravity");
+
+            for well in wells.iter_mut() {
+                well.update_position(objects, dt);
+            }
+            let states = objects
+                .iter()
+                .map(|o| (o.pos, (o.pos - o.pos_old) / dt))
+                .collect::<Vec<_>>();
+            if let Some(acc) = forces::accelerations(settings, wells, objects, &states, settings.time) {
+                objects
+                    .iter_mut()
+                    .zip(acc)
+                    .filter(|(o, _)| !o.is_asleep())
+                    .for_each(|(obj, v)| {
+                        if settings.gravity_set_velocity {
+                            obj.set_velocity(v * dt);
+                        } else {
+                            obj.accelerate(v);
+                        }
+                        #[cfg(feature = "panic-nan")]
+                        obj.panic_nan("gravity");
+                    });
+            }
+        }
+
+        // Handle bounds
+        if !matches!(settings.bounds, Bounds::None) {
+            #[cfg(feature = "tracy")]
+            profiling::scope!("bounds");
+            // Wrapping around moves kinematic objects as well.
+            let periodic = matches!(settings.bounds, Bounds::Periodic(_, _));
+            let movable = |o: &&mut PhysObject| !o.is_asleep() && (o.is_dynamic() || periodic);
+            objects.iter_mut().filter(movable).for_each(|obj| {
+                settings.bounds.update_position(obj);
+                #[cfg(feature = "panic-nan")]
+                obj.panic_nan("bounds");
+            });
+        }
+
+        // Handle walls
+        if let Some(grid) = wall_grid {
+            #[cfg(feature = "tracy")]
+            profiling::scope!("walls");
+            objects
+                .par_iter_mut()
+                .filter(|o| !o.is_asleep() && o.is_dynamic())
+                .for_each(|obj| {
+                    let mut near = Vec::new();
+                    grid.for_overlapping(obj.pos, obj.radius, |i| near.push(i));
+                    // The grid's order isn't stable, and pushing out of one wall can push the
+                    // object into another.
+                    near.sort_unstable();
+                    for i in near {
+                        walls[i].update_position(obj);
+                    }
+                    #[cfg(feature = "panic-nan")]
+                    obj.panic_nan("walls");
+                });
+        }
+
+        {
+            #[cfg(feature = "tracy")]
+            profiling::scope!("constraints");
+            let image = |axis| settings.bounds.min_image(axis);
+
+            for link in links.iter().filter(|l| !l.is_snapped()) {
+                let (a, b) = link.ends();
+                if !objects[*a].is_asleep() || !objects[*b].is_asleep() {
+                    link.apply(objects, image);
+                }
+            }
+
+            for point in points.iter() {
+                point.apply(objects, image);
+            }
+        }
+
+        // Handle collisions
+        if settings.collisions != CollisionMode::Off {
+            #[cfg(feature = "tracy")]
+            profiling::scope!("collisions");
+
+            // Objects near the edges of periodic bounds also take part as images on the other
+            // side, as (index, offset) after all objects.
+            let mut entries = (0..objects.len()).map(|i| (i, DVec2::ZERO)).collect::<Vec<_>>();
+            if let Bounds::Periodic(_, _) = settings.bounds {
+                let margin = 2.0 * objects.iter().fold(0.0, |r, o| o.radius.max(r));
+                for (i, obj) in objects.iter().enumerate() {
+                    entries.extend(settings.bounds.images(obj.pos, margin).map(|offset| (i, offset)));
+                }
+            }
+            let objs = &*objects;
+            let bounds = |(i, offset): &(usize, DVec2)| (objs[*i].pos + *offset, objs[*i].radius);
+            // Sleeping objects are in the grid to be found, but don't look for pairs themselves.
+            let grid = HGrid::new(&entries, bounds);
+            let broadphase = AwakeGrid::new(
+                &grid,
+                entries
+                    .iter()
+                    .map(|e| {
+                        let (pos, radius) = bounds(e);
+                        (pos, radius, !objs[e.0].is_asleep())
+                    })
+                    .collect(),
+            );
+            let mut pairs = entries.par_pairs(&broadphase, |(i, a_offset), (j, b_offset)| {
+                let (a, b) = (&objs[*i], &objs[*j]);
+                if i == j {
+                    return None;
+                }
+                // Of the two ways an image pair can be found, keep the one where the
+                // original has the lower index.
+                if *a_offset != DVec2::ZERO && (*b_offset != DVec2::ZERO || i < j)
+                    || *b_offset != DVec2::ZERO && j < i
+                {
+                    return None;
+                }
+                let collision_axis = (a.pos + *a_offset) - (b.pos + *b_offset);
+                let collision_axis = if collision_axis == DVec2::ZERO {
+                    DVec2::new(f64::EPSILON, f64::EPSILON)
+                } else {
+                    collision_axis
+                };
+                let combined = a.radius + b.radius;
+                let dist_sqr = collision_axis.length_squared();
+                if dist_sqr < combined * combined {
+                    let dist = dist_sqr.sqrt();
+                    let n = collision_axis / dist;
+                    let delta = combined - dist;
+                    let rel = (a.pos - a.pos_old) - (b.pos - b.pos_old);
+                    let dv = a.material.combine(b.material).response(rel, n);
+                    Some((*i, *j, n, delta, rel, dv))
+                } else {
+                    None
+                }
+            });
+            if settings.deterministic {
+                sort_pairs(&mut pairs);
+            }
+            let moving = |o: &PhysObject| {
+                !o.is_asleep() && (o.pos - o.pos_old).length() > settings.sleep_speed * dt
+            };
+            let mut merging = Vec::new();
+            let mut shattering = Vec::new();
+            for (_, _, (i, j, n, delta, rel, dv)) in pairs {
+                // Sleeping objects are woken when something moving touches them.
+                if objects[i].is_asleep() && moving(&objects[j]) {
+                    objects[i].wake();
+                }
+                if objects[j].is_asleep() && moving(&objects[i]) {
+                    objects[j].wake();
+                }
+                if settings.collisions == CollisionMode::Merge
+                    && objects[i].is_dynamic()
+                    && objects[j].is_dynamic()
+                {
+                    merging.push((i, j));
+                    continue;
+                }
+                *max_penetration = max_penetration.max(delta);
+                let speed = (-rel.dot(n)).max(0.0) / dt;
+                // Merging removes objects as well, only shatter when the indices stay put.
+                if settings.collisions == CollisionMode::Push {
+                    for (k, normal) in [(i, n), (j, -n)] {
+                        if objects[k].is_dynamic() && speed > objects[k].material.strength {
+                            shattering.push((k, normal, speed - objects[k].material.strength));
+                        }
+                    }
+                }
+                let (a_inv, b_inv) = (objects[i].inv_mass(), objects[j].inv_mass());
+                if a_inv + b_inv == 0.0 {
+                    continue;
+                }
+                let a_w = a_inv / (a_inv + b_inv);
+                let b_w = b_inv / (a_inv + b_inv);
+                let a = &mut objects[i];
+                a.pos += a_w * delta * n;
+                a.pos_old -= a_w * dv;
+                #[cfg(feature = "panic-nan")]
+                a.panic_nan("collision");
+                let b = &mut objects[j];
+                b.pos -= b_w * delta * n;
+                b.pos_old += b_w * dv;
+                #[cfg(feature = "panic-nan")]
+                b.panic_nan("collision");
+
+                if settings.collision_events {
+                    let (a, b, normal) = if i < j { (i, j, n) } else { (j, i, -n) };
+                    contacts.push(Contact {
+                        a,
+                        b,
+                        normal,
+                        depth: delta,
+                        relative_speed: (-rel.dot(n)).max(0.0) / dt,
+                    });
+                }
+            }
+
+            if !merging.is_empty() {
+                let mut gone = vec![false; objects.len()];
+                for (i, j) in merging {
+                    if gone[i] || gone[j] {
+                        continue;
+                    }
+                    let (into, from) = if objects[j].mass > objects[i].mass {
+                        (j, i)
+                    } else {
+                        (i, j)
+                    };
+                    let (a, b) = (objects[into].clone(), objects[from].clone());
+                    let mass = a.mass + b.mass;
+                    let w = if mass > 0.0 { b.mass / mass } else { 0.5 };
+                    let velocity = (a.pos - a.pos_old) * (1.0 - w) + (b.pos - b.pos_old) * w;
+                    let obj = &mut objects[into];
+                    obj.pos = a.pos + settings.bounds.min_image(b.pos - a.pos) * w;
+                    obj.pos_old = obj.pos - velocity;
+                    obj.acceleration = a.acceleration * (1.0 - w) + b.acceleration * w;
+                    // Keeps the density of the heavier object.
+                    obj.radius = if a.mass > 0.0 {
+                        a.radius * (mass / a.mass).sqrt()
+                    } else {
+                        a.radius.hypot(b.radius)
+                    };
+                    obj.mass = mass;
+                    obj.charge = a.charge + b.charge;
+                    obj.wake();
+                    #[cfg(feature = "panic-nan")]
+                    obj.panic_nan("merge");
+                    gone[from] = true;
+                    merges.push(Merge {
+                        into: a.key,
+                        from: b.key,
+                        weight: w,
+                    });
+                    links.iter_mut().for_each(|l| l.retarget(from, into));
+                    points.iter_mut().for_each(|p| p.retarget(from, into));
+                }
+                remove_objects(objects, links, points, contacts, &gone);
+                wells.iter_mut().for_each(|w| w.find_followed(objects));
+            }
+
+            if !shattering.is_empty() {
+                let mut gone = vec![false; objects.len()];
+                for (k, normal, excess) in shattering {
+                    let obj = objects[k].clone();
+                    // Equal fragments, as many as fit above the minimum radius.
+                    let count = ((obj.radius / settings.min_fragment_radius).powi(2) as u32)
+                        .min(MAX_FRAGMENTS)
+                        .min(*fragment_budget);
+                    if gone[k] || count < 2 {
+                        continue;
+                    }
+                    *fragment_budget -= count;
+                    gone[k] = true;
+                    let first = objects.len();
+                    let radius = obj.radius / (count as f64).sqrt();
+                    // Neighbours on the ring just touch, its chords are one diameter long.
+                    let ring = radius / (std::f64::consts::PI / count as f64).sin();
+                    // The ring is wider than the object, it's moved away from whatever was hit
+                    // so the fragments don't reach past the object on that side.
+                    let center = obj.pos + normal * (ring + radius - obj.radius).max(0.0);
+                    let velocity = obj.pos - obj.pos_old;
+                    // Fragments on a ring scattered along the normal, the kicks sum to zero so
+                    // momentum is kept.
+                    let offsets = (0..count).map(|f| {
+                        let angle = std::f64::consts::TAU * f as f64 / count as f64;
+                        (normal * angle.cos() + normal.perp() * angle.sin()) * ring
+                    });
+                    for offset in offsets {
+                        let kick = normal * (offset.dot(normal) / ring * excess * dt);
+                        let pos = center + offset;
+                        objects.push(PhysObject {
+                            pos,
+                            pos_old: pos - velocity - kick,
+                            radius,
+                            mass: obj.mass / count as f64,
+                            charge: obj.charge / count as f64,
+                            key: None,
+                            origin: obj.key.or(obj.origin),
+                            sleep: Sleep::default(),
+                            idle: 0,
+                            ..obj.clone()
+                        });
+                        gone.push(false);
+                    }
+                    for link in links.iter_mut() {
+                        let (a, b) = link.ends();
+                        if *a == k || *b == k {
+                            link.snap();
+                        }
+                    }
+                    points.iter_mut().for_each(|p| p.retarget(k, first));
+                }
+                remove_objects(objects, links, points, contacts, &gone);
+                wells.iter_mut().for_each(|w| w.find_followed(objects));
+            }
+        }
+
+        // Update positions
+        {
+            #[cfg(feature = "tracy")]
+            profiling::scope!("update");
+            settings.integrator.integrate(settings, wells, objects, dt);
+        }
+
+        // Continuous collision detection
+        if objects.iter().any(|o| o.ccd && !o.is_asleep()) {
+            #[cfg(feature = "tracy")]
+            profiling::scope!("ccd");
+
+            // Earliest impact of every CCD object during this step.
+            let mut impacts = objects
+                .iter()
+                .map(|o| {
+                    (o.ccd && !o.is_asleep())
+                        .then(|| {
+                            let t = settings.bounds.time_of_impact(o.pos_old, o.pos, o.radius)?;
+                            let pos = o.pos_old + (o.pos - o.pos_old) * t;
+                            Some(Impact {
+                                t,
+                                normal: settings.bounds.normal(pos),
+                                other: DVec2::ZERO,
+                                weight: 1.0,
+                                material: o.material,
+                            })
+                        })
+                        .flatten()
+                })
+                .collect::<Vec<_>>();
+
+            if let Some(grid) = wall_grid {
+                for (obj, impact) in objects.iter().zip(impacts.iter_mut()) {
+                    if !obj.ccd || obj.is_asleep() || !obj.is_dynamic() {
+                        continue;
+                    }
+                    let half = (obj.pos - obj.pos_old) / 2.0;
+                    grid.for_overlapping(obj.pos_old + half, obj.radius + half.length(), |i| {
+                        if let Some((t, normal)) =
+                            walls[i].time_of_impact(obj.pos_old, obj.pos, obj.radius)
+                            && impact.map_or(true, |s| t < s.t)
+                        {
+                            *impact = Some(Impact {
+                                t,
+                                normal,
+                                other: DVec2::ZERO,
+                                weight: 1.0,
+                                material: obj.material,
+                            });
+                        }
+                    });
+                }
+            }
+
+            if settings.collisions != CollisionMode::Off {
+                // Objects are put in the grid with their whole sweep.
+                let grid = HGrid::new(objects.as_slice(), |o| {
+                    let half = (o.pos - o.pos_old) / 2.0;
+                    (o.pos_old + half, o.radius + half.length())
+                });
+                let pairs = objects.par_pairs(&grid, |a, b| {
+                    if !(a.ccd && !a.is_asleep()) && !(b.ccd && !b.is_asleep()) {
+                        return None;
+                    }
+                    let t = sweep(a, b)?;
+                    let at = |o: &PhysObject| o.pos_old + (o.pos - o.pos_old) * t;
+                    Some((t, (at(a) - at(b)).normalize_or_zero()))
+                });
+                // Only the earliest impact is kept, so the order doesn't matter here.
+                for (i, j, (t, n)) in pairs {
+                    let (a_inv, b_inv) = (objects[i].inv_mass(), objects[j].inv_mass());
+                    if a_inv + b_inv == 0.0 {
+                        continue;
+                    }
+                    let material = objects[i].material.combine(objects[j].material);
+                    for (k, other, normal, inv) in [(i, j, n, a_inv), (j, i, -n, b_inv)] {
+                        let obj = &objects[k];
+                        if obj.ccd && !obj.is_asleep() && impacts[k].map_or(true, |s| t < s.t) {
+                            impacts[k] = Some(Impact {
+                                t,
+                                normal,
+                                other: objects[other].pos - objects[other].pos_old,
+                                weight: inv / (a_inv + b_inv),
+                                material,
+                            });
+                        }
+                    }
+                }
+            }
+
+            for (obj, impact) in objects.iter_mut().zip(impacts) {
+                if let Some(impact) = impact {
+                    obj.rewind(impact.t);
+                    // The approach is taken out at the impact, otherwise the next step starts
+                    // out touching and the sweep has nothing left to find.
+                    let rel = (obj.pos - obj.pos_old) - impact.other;
+                    let vn = rel.dot(impact.normal);
+                    if vn < 0.0 {
+                        let response = impact.material.response(rel, impact.normal);
+                        obj.pos_old -= (response - impact.normal * vn) * impact.weight;
+                    }
+                    #[cfg(feature = "panic-nan")]
+                    obj.panic_nan("ccd");
+                }
+            }
+        }
+
+        // Handle sleeping
+        if settings.sleeping {
+            #[cfg(feature = "tracy")]
+            profiling::scope!("sleep");
+
+            objects
+                .iter_mut()
+                .filter(|o| !o.is_asleep())
+                .for_each(|o| o.update_idle(settings.sleep_speed, dt));
+
+            // Linked objects form islands that fall asleep and wake up together.
+            let mut islands = UnionFind::new(objects.len());
+            for link in links.iter().filter(|l| !l.is_snapped()) {
+                let (a, b) = link.ends();
+                islands.union(*a, *b);
+            }
+            let mut idle = vec![true; objects.len()];
+            for (i, obj) in objects.iter().enumerate() {
+                idle[islands.find(i)] &= obj.is_idle(settings.sleep_steps);
+            }
+            for (i, obj) in objects.iter_mut().enumerate() {
+                let idle = idle[islands.find(i)];
+                if idle && !obj.is_asleep() {
+                    obj.fall_asleep();
+                } else if !idle && obj.is_asleep() {
+                    obj.wake();
+                }
+            }
+        }
+    }
+}
+
+/// Earliest impact of a CCD object during a step.
+#[derive(Clone, Copy)]
+struct Impact {
+    /// Fraction of the step.
+    t: f64,
+    /// Points away from what was hit.
+    normal: DVec2,
+    /// Velocity of what was hit.
+    other: DVec2,
+    /// Share of the response that goes to this object.
+    weight: f64,
+    material: Material,
+}
+
+/// When two objects moving from `pos_old` to `pos` first touch, as a fraction of the step.
+/// Objects that already touch at the start count as an impact right away if they're still
+/// approaching each other.
+fn sweep(a: &PhysObject, b: &PhysObject) -> Option<f64> {
+    let start = a.pos_old - b.pos_old;
+    let motion = (a.pos - a.pos_old) - (b.pos - b.pos_old);
+    let combined = a.radius + b.radius;
+    let c = start.length_squared() - combined * combined;
+    let qa = motion.length_squared();
+    let qb = 2.0 * start.dot(motion);
+    if qa == 0.0 {
+        return None;
+    }
+    if c <= 0.0 {
+        return (qb < 0.0).then_some(0.0);
+    }
+    if qb >= 0.0 {
+        return None;
+    }
+    let discriminant = qb * qb - 4.0 * qa * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let t = (-qb - discriminant.sqrt()) / (2.0 * qa);
+    (0.0..=1.0).contains(&t).then_some(t)
+}
+
+/// Most fragments an object shatters into.
+const MAX_FRAGMENTS: u32 = 8;
+
+/// Removes the objects marked as `gone`, nothing may refer to them anymore.
+fn remove_objects(
+    objects: &mut Vec<PhysObject>,
+    links: &mut [LinkConstraint<usize>],
+    points: &mut [PointConstraint<usize>],
+    contacts: &mut Vec<Contact>,
+    gone: &[bool],
+) {
+    let mut remap = Vec::with_capacity(gone.len());
+    let mut kept = 0;
+    for gone in gone {
+        remap.push(kept);
+        if !gone {
+            kept += 1;
+        }
+    }
+    let mut i = 0;
+    objects.retain(|_| {
+        i += 1;
+        !gone[i - 1]
+    });
+    for link in links.iter_mut() {
+        *link = link.try_map(|i| Some(remap[*i])).unwrap();
+    }
+    for point in points.iter_mut() {
+        *point = point.try_map(|i| Some(remap[*i])).unwrap();
+    }
+    contacts.retain(|c| !gone[c.a] && !gone[c.b]);
+    for contact in contacts.iter_mut() {
+        contact.a = remap[contact.a];
+        contact.b = remap[contact.b];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec2;
+
+    use super::Simulation;
+    use crate::physics::{CollisionMode, ForceField, ObjectPos, PhysObject, PhysSettings};
+
+    const DT: f64 = 1.0 / 60.0;
+
+    #[test]
+    fn free_fall() {
+        let mut sim = Simulation::new(PhysSettings {
+            fields: vec![ForceField::default()],
+            ..Default::default()
+        });
+        sim.objects.push(PhysObject::new(DVec2::ZERO, 4.0, 1.0));
+        for _ in 0..60 {
+            sim.step(DT);
+        }
+        // One second at 400 units/s², Verlet is off by less than a step's worth of fall.
+        let obj = &sim.objects[0];
+        assert!((obj.pos().y + 200.0).abs() < 400.0 * DT, "{}", obj.pos().y);
+        assert!((obj.velocity(sim.sub_dt()).y + 400.0).abs() < 1e-6);
+        assert_eq!(obj.pos().x, 0.0);
+    }
+
+    #[test]
+    fn collision_keeps_momentum() {
+        let mut sim = Simulation::new(PhysSettings::default());
+        let moving = PhysObject::new(DVec2::new(-20.0, 1.0), 4.0, 1.0);
+        sim.objects.push(moving.with_velocity(DVec2::X * 300.0, DT));
+        sim.objects.push(PhysObject::new(DVec2::new(20.0, 0.0), 8.0, 1.0));
+        let momentum = |sim: &Simulation, dt: f64| {
+            sim.objects
+                .iter()
+                .fold(DVec2::ZERO, |p, o| p + o.velocity(dt) * o.mass())
+        };
+        let before = momentum(&sim, DT);
+        for _ in 0..30 {
+            sim.step(DT);
+        }
+        let after = momentum(&sim, sim.sub_dt());
+        // The objects did hit each other.
+        assert!(sim.objects[1].velocity(sim.sub_dt()).x > 0.0);
+        assert!((after - before).length() < 1e-6 * before.length(), "{before} {after}");
+    }
+
+    #[test]
+    fn merge_at_rest_moves_to_center_of_mass() {
+        let mut sim = Simulation::new(PhysSettings {
+            collisions: CollisionMode::Merge,
+            ..Default::default()
+        });
+        sim.objects.push(PhysObject::new(DVec2::ZERO, 4.0, 1.0));
+        sim.objects.push(PhysObject::new(DVec2::new(6.0, 0.0), 4.0, 3.0));
+        let stored = ObjectPos {
+            current: DVec2::ZERO,
+            old: DVec2::ZERO,
+            prev: DVec2::ZERO,
+        };
+        sim.step(DT);
+        assert_eq!(sim.objects.len(), 1);
+        let obj = &sim.objects[0];
+        assert!(obj.pos().distance(DVec2::new(4.5, 0.0)) < 1e-9, "{}", obj.pos());
+        // Nothing is left moving, the position still has to be written back.
+        assert_eq!(obj.velocity(sim.sub_dt()), DVec2::ZERO);
+        assert!(stored.differs(obj));
+    }
+
+    #[test]
+    fn deterministic_across_thread_counts() {
+        let run = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| {
+                let mut sim = Simulation::new(PhysSettings {
+                    gravitational_constant: 1e4,
+                    deterministic: true,
+                    ..Default::default()
+                });
+                // A crowded cloud that collapses, so there are plenty of contacts to order.
+                for i in 0..400 {
+                    let (x, y) = ((i % 20) as f64 * 7.0, (i / 20) as f64 * 7.0);
+                    let obj = PhysObject::new(DVec2::new(x - 70.0, y - 70.0), 4.0, 1.0);
+                    let v = DVec2::new((i * 7 % 13) as f64, (i * 5 % 11) as f64) - 6.0;
+                    sim.objects.push(obj.with_velocity(v, DT));
+                }
+                for _ in 0..60 {
+                    sim.step(DT);
+                }
+                sim.objects
+                    .iter()
+                    .map(|o| o.pos().to_array())
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(run(1), run(4));
+    }
+}
@@ -0,0 +1,156 @@
+use bevy::{math::DVec2, prelude::*};
+
+use super::PhysObject;
+
+/// How thick walls are drawn, they collide as infinitely thin lines.
+const THICKNESS: f32 = 2.0;
+
+/// Static polyline that objects collide against.
+#[derive(Component)]
+pub struct Wall {
+    points: Vec<DVec2>,
+}
+
+impl Wall {
+    pub fn segments(&self) -> impl Iterator<Item = Segment> + '_ {
+        self.points.windows(2).map(|p| Segment { a: p[0], b: p[1] })
+    }
+
+    /// Spawns the wall with a sprite for each of its segments.
+    pub fn spawn(commands: &mut Commands, points: Vec<DVec2>, color: Color) -> Entity {
+        let wall = Wall { points };
+        let sprites = wall
+            .segments()
+            .map(|s| {
+                let axis = (s.b - s.a).as_vec2();
+                let center = ((s.a + s.b) / 2.0).as_vec2();
+                SpriteBundle {
+                    sprite: Sprite {
+                        color,
+                        custom_size: Some(Vec2::new(axis.length() + THICKNESS, THICKNESS)),
+                        ..default()
+                    },
+                    transform: Transform {
+                        translation: center.extend(0.0),
+                        rotation: Quat::from_rotation_z(axis.y.atan2(axis.x)),
+                        ..default()
+                    },
+                    ..default()
+                }
+            })
+            .collect::<Vec<_>>();
+        commands
+            .spawn()
+            .insert(wall)
+            .insert(Transform::default())
+            .insert(GlobalTransform::default())
+            .with_children(|parent| {
+                for sprite in sprites {
+                    parent.spawn_bundle(sprite);
+                }
+            })
+            .id()
+    }
+}
+
+/// One straight piece of a wall as the simulation sees it.
+#[derive(Clone, Copy)]
+pub struct Segment {
+    pub a: DVec2,
+    pub b: DVec2,
+}
+
+impl Segment {
+    /// Center and radius of a circle around the segment.
+    #[inline(always)]
+    pub fn bounds(&self) -> (DVec2, f64) {
+        ((self.a + self.b) / 2.0, self.a.distance(self.b) / 2.0)
+    }
+
+    #[inline(always)]
+    fn closest_point(&self, p: DVec2) -> DVec2 {
+        let axis = self.b - self.a;
+        let len_sqr = axis.length_squared();
+        if len_sqr == 0.0 {
+            return self.a;
+        }
+        let t = ((p - self.a).dot(axis) / len_sqr).clamp(0.0, 1.0);
+        self.a + axis * t
+    }
+
+    /// Pushes the object out of the segment and applies its material.
+    #[inline(always)]
+    pub fn update_position(&self, obj: &mut PhysObject) {
+        let min = self.a.min(self.b) - DVec2::splat(obj.radius);
+        let max = self.a.max(self.b) + DVec2::splat(obj.radius);
+        if obj.pos.cmplt(min).any() || obj.pos.cmpgt(max).any() {
+            return;
+        }
+        let axis = obj.pos - self.closest_point(obj.pos);
+        let dist_sqr = axis.length_squared();
+        if dist_sqr >= obj.radius * obj.radius {
+            return;
+        }
+        let dist = dist_sqr.sqrt();
+        let normal = if dist == 0.0 {
+            // Exactly on the line, push out on the side the object came from.
+            let perp = (self.b - self.a).perp().normalize_or_zero();
+            if (obj.pos_old - self.a).dot(perp) < 0.0 {
+                -perp
+            } else {
+                perp
+            }
+        } else {
+            axis / dist
+        };
+        let velocity = obj.pos - obj.pos_old;
+        obj.pos += normal * (obj.radius - dist);
+        obj.bounce(normal, velocity);
+    }
+
+    /// When an object moving from `from` to `to` first touches the segment, as a fraction of
+    /// the movement, and the normal pointing away from the segment there. Objects that already
+    /// touch it at the start are left to `update_position`.
+    pub fn time_of_impact(&self, from: DVec2, to: DVec2, radius: f64) -> Option<(f64, DVec2)> {
+        if from.distance_squared(self.closest_point(from)) < radius * radius {
+            return None;
+        }
+        let d = to - from;
+        let mut hit: Option<(f64, DVec2)> = None;
+        let mut keep = |t: f64, normal: DVec2| {
+            if (0.0..=1.0).contains(&t) && hit.map_or(true, |(s, _)| t < s) {
+                hit = Some((t, normal));
+            }
+        };
+
+        // The side of the segment facing the object.
+        let axis = self.b - self.a;
+        let n = axis.perp().normalize_or_zero();
+        if n != DVec2::ZERO {
+            let n = if (from - self.a).dot(n) < 0.0 { -n } else { n };
+            let speed = d.dot(n);
+            if speed < 0.0 {
+                let t = ((from - self.a).dot(n) - radius) / -speed;
+                let along = (from + d * t - self.a).dot(axis) / axis.length_squared();
+                if (0.0..=1.0).contains(&along) {
+                    keep(t, n);
+                }
+            }
+        }
+
+        // The ends, the same as sweeping against a point.
+        for end in [self.a, self.b] {
+            let start = from - end;
+            let c = start.length_squared() - radius * radius;
+            let qa = d.length_squared();
+            let qb = 2.0 * start.dot(d);
+            let discriminant = qb * qb - 4.0 * qa * c;
+            if qa > 0.0 && qb < 0.0 && discriminant >= 0.0 {
+                let t = (-qb - discriminant.sqrt()) / (2.0 * qa);
+                keep(t, (from + d * t - end).normalize_or_zero());
+            }
+        }
+
+        hit
+    }
+}
@@ -0,0 +1,137 @@
+use bevy::{math::DVec2, prelude::*};
+
+use super::{nbody::PairForce, ForceLaw, PhysObject};
+
+/// How big wells are drawn, they have no size in the simulation.
+const MARKER_SIZE: f32 = 12.0;
+
+/// How a gravity well moves.
+#[derive(Clone, Copy, PartialEq)]
+pub enum WellMotion {
+    Pinned,
+    /// Moves with a constant velocity.
+    Moving(DVec2),
+    /// Stays on top of an object, and is left where it was once the object is gone.
+    Following(Entity),
+}
+
+impl WellMotion {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WellMotion::Pinned => "Pinned",
+            WellMotion::Moving(_) => "Moving",
+            WellMotion::Following(_) => "Following",
+        }
+    }
+}
+
+/// A point every object is attracted to, it isn't moved by the objects itself.
+#[derive(Component, Clone)]
+pub struct GravityWell {
+    pub pos: DVec2,
+    /// Pull at unit distance, negative wells push objects away.
+    pub strength: f64,
+    pub law: ForceLaw,
+    pub motion: WellMotion,
+}
+
+impl GravityWell {
+    pub fn new(pos: DVec2) -> Self {
+        Self {
+            pos,
+            strength: 4_000_000.0,
+            law: ForceLaw::InverseSquare,
+            motion: WellMotion::Pinned,
+        }
+    }
+
+    /// Whether `pos` is on the well's marker.
+    pub fn contains(&self, pos: DVec2) -> bool {
+        self.pos.distance(pos) < MARKER_SIZE as f64 / 2.0
+    }
+
+    /// Spawns the well with its marker.
+    pub fn spawn(commands: &mut Commands, well: GravityWell, image: Handle<Image>) -> Entity {
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::ORANGE,
+                    custom_size: Some(Vec2::splat(MARKER_SIZE)),
+                    ..default()
+                },
+                // Drawn on top of the objects.
+                transform: Transform::from_translation(well.pos.as_vec2().extend(1.0)),
+                texture: image,
+                ..default()
+            })
+            .insert(well)
+            .id()
+    }
+}
+
+/// A gravity well as seen by the simulation.
+#[derive(Clone, Copy)]
+pub struct Well {
+    pub pos: DVec2,
+    velocity: DVec2,
+    /// Key of the object the well follows.
+    follow: Option<usize>,
+    /// Index of that object, set by `find_followed`.
+    following: Option<usize>,
+    force: PairForce,
+}
+
+impl Well {
+    /// `follow` is the key of the followed object, if there is one.
+    pub fn new(well: &GravityWell, follow: Option<usize>, softening: f64) -> Self {
+        Self {
+            pos: well.pos,
+            velocity: match well.motion {
+                WellMotion::Moving(velocity) => velocity,
+                _ => DVec2::ZERO,
+            },
+            follow,
+            following: None,
+            force: PairForce {
+                constant: well.strength,
+                softening,
+                law: well.law,
+            },
+        }
+    }
+
+    /// Looks up the index of the followed object, which has to be done again whenever objects
+    /// are removed.
+    pub(super) fn find_followed(&mut self, objects: &[PhysObject]) {
+        self.following = self
+            .follow
+            .and_then(|key| objects.iter().position(|o| o.key == Some(key)));
+    }
+
+    pub(super) fn update_position(&mut self, objects: &[PhysObject], dt: f64) {
+        self.pos += self.velocity * dt;
+        if let Some(i) = self.following {
+            self.pos = objects[i].pos;
+        }
+    }
+
+    /// Acceleration of an object at `pos`, `image` maps offsets to their minimum image.
+    #[inline(always)]
+    pub(super) fn acceleration(&self, pos: DVec2, image: impl Fn(DVec2) -> DVec2) -> DVec2 {
+        self.force.acceleration(image(self.pos - pos), 1.0)
+    }
+
+    /// Potential at `pos`, like `acceleration`.
+    #[inline(always)]
+    pub(super) fn potential(&self, pos: DVec2, image: impl Fn(DVec2) -> DVec2) -> f64 {
+        self.force.potential(image(self.pos - pos), 1.0)
+    }
+}
+
+pub(super) fn update_wells_system(
+    mut wells: Query<(&GravityWell, &mut Transform), Changed<GravityWell>>,
+) {
+    for (well, mut transform) in wells.iter_mut() {
+        transform.translation = well.pos.as_vec2().extend(1.0);
+    }
+}
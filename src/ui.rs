@@ -18,7 +18,7 @@ use massi::{
 #[cfg(feature = "math")]
 use bevy_egui::egui::Rgba;
 
-use crate::{physics::{Bounds, Gravity, LinkConstraint, ObjectPos, PhysSettings, PointConstraint}, PlacementSettings};
+use physics::{BodyKind, Bounds, CollisionEvent, CollisionMode, ForceField, ForceLaw, Gravity, GravityWell, Integrator, LinkConstraint, MagneticField, NBody, ObjectPos, PhysDiagnostics, PhysSettings, PhysTime, PlacementSettings, PointConstraint, SimRng, Wall, WellMotion};
 
 #[cfg(feature = "math")]
 enum ExprRes {
@@ -33,8 +33,9 @@ impl Default for ExprRes {
     }
 }
 
+/// Expressions being edited for a force field layer.
 #[derive(Default)]
-pub struct State {
+struct FieldState {
     #[cfg(feature = "math")]
     expr_x: ExprRes,
     #[cfg(feature = "math")]
@@ -45,15 +46,37 @@ pub struct State {
     y_compile_err: Option<ModuleError>,
 }
 
+/// Expression being edited for the magnetic field.
+#[cfg(feature = "math")]
+#[derive(Default)]
+struct MagneticState {
+    expr: ExprRes,
+    compile_err: Option<ModuleError>,
+}
+
+#[derive(Default)]
+pub struct State {
+    #[cfg(feature = "math")]
+    magnetic: MagneticState,
+    /// One for each of `PhysSettings::fields`.
+    fields: Vec<FieldState>,
+}
+
 pub fn ui(
     mut commands: Commands,
     mut objects: Query<(Entity, &mut ObjectPos)>,
     links: Query<(Entity, &LinkConstraint<Entity>)>,
     points: Query<(Entity, &PointConstraint<Entity>)>,
+    walls: Query<Entity, With<Wall>>,
+    mut wells: Query<(Entity, &mut GravityWell)>,
     mut egui_context: ResMut<EguiContext>,
     mut settings: ResMut<PhysSettings>,
     mut placement: ResMut<PlacementSettings>,
+    mut rng: ResMut<SimRng>,
     diagnostics: Res<Diagnostics>,
+    phys_diagnostics: Res<PhysDiagnostics>,
+    phys_time: Res<PhysTime>,
+    mut collision_events: EventReader<CollisionEvent>,
     mut state: Local<State>,
 ) {
     #[cfg(feature = "tracy")]
@@ -76,108 +99,327 @@ pub fn ui(
     egui::SidePanel::new(Side::Left, "settings").show(egui_context.ctx_mut(), |ui| {
         ui.heading("Settings");
 
-        let mut curr = settings.bounds.as_str();
-        egui::ComboBox::from_label("Bounds")
-            .selected_text(curr)
-            .show_ui(ui, |ui| {
-                ui.selectable_value(&mut curr, "Circle", "Circle");
-                ui.selectable_value(&mut curr, "Rect", "Rect");
-                ui.selectable_value(&mut curr, "None", "None");
-            });
+        fn bounds_edit(ui: &mut egui::Ui, label: &str, bounds: &mut Bounds) {
+            let mut curr = bounds.as_str();
+            egui::ComboBox::from_label(label)
+                .selected_text(curr)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut curr, "Circle", "Circle");
+                    ui.selectable_value(&mut curr, "Rect", "Rect");
+                    ui.selectable_value(&mut curr, "Polygon", "Polygon");
+                    ui.selectable_value(&mut curr, "Union", "Union");
+                    ui.selectable_value(&mut curr, "Intersection", "Intersection");
+                    ui.selectable_value(&mut curr, "Exclusion", "Exclusion");
+                    ui.selectable_value(&mut curr, "Periodic", "Periodic");
+                    ui.selectable_value(&mut curr, "None", "None");
+                });
 
-        settings.bounds = Bounds::from_str(curr, settings.bounds.clone());
+            *bounds = Bounds::from_str(curr, std::mem::replace(bounds, Bounds::None));
 
-        match &mut settings.bounds {
-            Bounds::Circle(radius) => {
-                scalar(ui, "Radius", radius);
-                *radius = radius.max(0.0);
+            match bounds {
+                Bounds::Circle(radius) => {
+                    scalar(ui, "Radius", radius);
+                    *radius = radius.max(0.0);
+                }
+                Bounds::Rect(min, max) | Bounds::Periodic(min, max) => {
+                    vector(ui, "Min", min);
+                    vector(ui, "Max", max);
+                    *min = min.min(*max);
+                    *max = max.max(*min);
+                }
+                Bounds::Polygon(points) => {
+                    let mut remove = None;
+                    for (i, point) in points.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            vector(ui, &format!("{}", i), point);
+                            if ui.small_button("x").clicked() {
+                                remove = Some(i);
+                            }
+                        });
+                    }
+                    if let Some(i) = remove && points.len() > 3 {
+                        points.remove(i);
+                    }
+                    if ui.button("Add Vertex").clicked() {
+                        let (first, last) = (points[0], points[points.len() - 1]);
+                        points.push((first + last) / 2.0);
+                    }
+                }
+                Bounds::Union(a, b) | Bounds::Intersection(a, b) | Bounds::Exclusion(a, b) => {
+                    // Nested combo boxes need their own ids.
+                    ui.push_id(label, |ui| {
+                        ui.group(|ui| {
+                            bounds_edit(ui, "A", a);
+                            ui.push_id("b", |ui| bounds_edit(ui, "B", b));
+                        });
+                    });
+                }
+                Bounds::None => {}
+            }
+        }
+        bounds_edit(ui, "Bounds", &mut settings.bounds);
+
+        #[cfg(feature = "math")]
+        fn compile_text(ui: &mut egui::Ui, expr: &mut String, ex: &mut ExprRes) -> bool {
+            let res = ui.text_edit_singleline(expr);
+            let mut changed = false;
+            if res.changed() {
+                match Expr::try_from(expr.as_str()).map(|e| e.simplify()) {
+                    Ok(e) => {
+                        *ex = ExprRes::Expr(e);
+                        changed = true;
+                    }
+                    Err(e) => {
+                        *ex = ExprRes::Error(e);
+                    }
+                }
             }
-            Bounds::Rect(min, max) => {
-                vector(ui, "Min", min);
-                vector(ui, "Max", max);
-                *min = min.min(*max);
-                *max = max.max(*min);
+            if let ExprRes::Error(e) = ex {
+                for e in e {
+                    ui.colored_label(Rgba::RED, format!("{:?}", e));
+                }
             }
-            Bounds::None => {}
+            changed
         }
 
-        let mut curr = settings.gravity.as_str();
-        egui::ComboBox::from_label("Gravity")
+        #[cfg_attr(not(feature = "math"), allow(unused_variables))]
+        fn gravity_edit(ui: &mut egui::Ui, gravity: &mut Gravity, state: &mut FieldState) {
+            let mut curr = gravity.as_str();
+            egui::ComboBox::from_label("Gravity")
+                .selected_text(curr)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut curr, "Dir", "Dir");
+                    #[cfg(feature = "math")]
+                    ui.selectable_value(&mut curr, "Vector Field", "Vector Field");
+                });
+            *gravity = Gravity::from_str(curr, gravity.clone());
+
+            match gravity {
+                Gravity::Dir(dir) => {
+                    vector(ui, "Dir", dir);
+                }
+                #[cfg(feature = "math")]
+                Gravity::VectorField { x, y, funcs } => {
+                    ui.label("Functions of x, y and the time t");
+                    let mut changed = compile_text(ui, x, &mut state.expr_x);
+                    if let Some(err) = &state.x_compile_err {
+                        ui.colored_label(Rgba::RED, format!("{}", err));
+                    }
+                    changed |= compile_text(ui, y, &mut state.expr_y);
+                    if let Some(err) = &state.y_compile_err {
+                        ui.colored_label(Rgba::RED, format!("{}", err));
+                    }
+                    if changed && let (ExprRes::Expr(x), ExprRes::Expr(y)) = (&state.expr_x, &state.expr_y) {
+                        let args = &[Identifier::from('x'), Identifier::from('y'), Identifier::from('t')];
+                        match (compile(x, args), compile(y, args)) {
+                            (Ok(x), Ok(y)) => {
+                                *funcs = Some((x, y));
+                                state.x_compile_err = None;
+                                state.y_compile_err = None;
+                            }
+                            (Err(e), Ok(_)) => {
+                                state.x_compile_err = Some(e);
+                                state.y_compile_err = None;
+                            }
+                            (Ok(_), Err(e)) => {
+                                state.x_compile_err = None;
+                                state.y_compile_err = Some(e);
+                            }
+                            (Err(e0), Err(e1)) => {
+                                state.x_compile_err = Some(e0);
+                                state.y_compile_err = Some(e1);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        ui.label("Force Fields");
+        state.fields.resize_with(settings.fields.len(), Default::default);
+        let (mut remove, mut raise) = (None, None);
+        let count = settings.fields.len();
+        for (i, (field, field_state)) in settings.fields.iter_mut().zip(&mut state.fields).enumerate() {
+            ui.push_id(i, |ui| {
+                ui.group(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut field.enabled, format!("Layer {}", i));
+                        if ui.add_enabled(i > 0, egui::Button::new("⏶").small()).clicked() {
+                            raise = Some(i);
+                        }
+                        if ui.add_enabled(i + 1 < count, egui::Button::new("⏷").small()).clicked() {
+                            raise = Some(i + 1);
+                        }
+                        if ui.small_button("x").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                    gravity_edit(ui, &mut field.gravity, field_state);
+                    bounds_edit(ui, "Region", &mut field.region);
+                });
+            });
+        }
+        if let Some(i) = raise {
+            settings.fields.swap(i - 1, i);
+            state.fields.swap(i - 1, i);
+        }
+        if let Some(i) = remove {
+            settings.fields.remove(i);
+            state.fields.remove(i);
+        }
+        if ui.button("Add Layer").clicked() {
+            settings.fields.push(ForceField::default());
+        }
+
+        ui.label("Gravitational Constant");
+        ui.add(egui::DragValue::new(&mut settings.gravitational_constant));
+        ui.label("Coulomb Constant");
+        ui.add(egui::DragValue::new(&mut settings.coulomb_constant));
+
+        let mut curr = settings.magnetic_field.as_str();
+        egui::ComboBox::from_label("Magnetic Field")
             .selected_text(curr)
             .show_ui(ui, |ui| {
-                ui.selectable_value(&mut curr, "Dir", "Dir");
+                ui.selectable_value(&mut curr, "Uniform", "Uniform");
                 #[cfg(feature = "math")]
-                ui.selectable_value(&mut curr, "Vector Field", "Vector Field");
-                ui.selectable_value(&mut curr, "None", "None");
+                ui.selectable_value(&mut curr, "Expression", "Expression");
             });
-        settings.gravity = Gravity::from_str(curr, settings.gravity.clone());
+        settings.magnetic_field = MagneticField::from_str(curr, settings.magnetic_field.clone());
 
-        match &mut settings.gravity {
-            Gravity::Dir(dir) => {
-                vector(ui, "Dir", dir);
+        match &mut settings.magnetic_field {
+            MagneticField::Uniform(b) => {
+                scalar(ui, "B", b);
             }
-            Gravity::None => {}
             #[cfg(feature = "math")]
-            Gravity::VectorField { x, y, funcs } => {
-                fn compile_text(ui: &mut egui::Ui, expr: &mut String, ex: &mut ExprRes) -> bool {
-                    let res = ui.text_edit_singleline(expr);
-                    let mut changed = false;
-                    if res.changed() {
-                        match Expr::try_from(expr.as_str()).map(|e| e.simplify()) {
-                            Ok(e) => {
-                                *ex = ExprRes::Expr(e);
-                                changed = true;
-                            }
-                            Err(e) => {
-                                *ex = ExprRes::Error(e);
-                            }
+            MagneticField::Expression { func, expr } => {
+                let magnetic = &mut state.magnetic;
+                if compile_text(ui, expr, &mut magnetic.expr) && let ExprRes::Expr(e) = &magnetic.expr {
+                    let args = &[Identifier::from('x'), Identifier::from('y'), Identifier::from('t')];
+                    match compile(e, args) {
+                        Ok(f) => {
+                            *func = Some(f);
+                            magnetic.compile_err = None;
                         }
+                        Err(e) => magnetic.compile_err = Some(e),
                     }
-                    if let ExprRes::Error(e) = ex {
-                        for e in e {
-                            ui.colored_label(Rgba::RED, format!("{:?}", e));
-                        }
-                    }
-                    changed
                 }
-                let mut changed = compile_text(ui, x, &mut state.expr_x);
-                if let Some(err) = &state.x_compile_err {
+                if let Some(err) = &magnetic.compile_err {
                     ui.colored_label(Rgba::RED, format!("{}", err));
                 }
-                changed |= compile_text(ui, y, &mut state.expr_y);
-                if let Some(err) = &state.y_compile_err {
-                    ui.colored_label(Rgba::RED, format!("{}", err));
+            }
+        }
+
+        let mut curr = settings.n_body.as_str();
+        egui::ComboBox::from_label("N-Body")
+            .selected_text(curr)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut curr, "Exact", "Exact");
+                ui.selectable_value(&mut curr, "Barnes-Hut", "Barnes-Hut");
+            });
+        settings.n_body = NBody::from_str(curr, settings.n_body);
+
+        if let NBody::BarnesHut(theta) = &mut settings.n_body {
+            ui.horizontal(|ui| {
+                ui.label("θ");
+                ui.add(egui::Slider::new(theta, 0.0..=2.0));
+            });
+        }
+
+        fn force_law_edit(ui: &mut egui::Ui, label: &str, law: &mut ForceLaw) {
+            let mut curr = law.as_str();
+            egui::ComboBox::from_label(label)
+                .selected_text(curr)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut curr, "Inverse Square", "Inverse Square");
+                    ui.selectable_value(&mut curr, "Power", "Power");
+                    ui.selectable_value(&mut curr, "Yukawa", "Yukawa");
+                });
+            *law = ForceLaw::from_str(curr, *law);
+
+            match law {
+                ForceLaw::InverseSquare => {}
+                ForceLaw::Power(n) => {
+                    ui.horizontal(|ui| {
+                        ui.label("Exponent");
+                        ui.add(egui::DragValue::new(n).speed(0.01));
+                    });
                 }
-                if changed && let (ExprRes::Expr(x), ExprRes::Expr(y)) = (&state.expr_x, &state.expr_y) {
-                    let args = &[Identifier::from('x'), Identifier::from('y')];
-                    match (compile(x, args), compile(y, args)) {
-                        (Ok(x), Ok(y)) => {
-                            *funcs = Some((x, y));
-                            state.x_compile_err = None;
-                            state.y_compile_err = None;
-                        }
-                        (Err(e), Ok(_)) => {
-                            state.x_compile_err = Some(e);
-                            state.y_compile_err = None;
-                        }
-                        (Ok(_), Err(e)) => {
-                            state.x_compile_err = None;
-                            state.y_compile_err = Some(e);
-                        }
-                        (Err(e0), Err(e1)) => {
-                            state.x_compile_err = Some(e0);
-                            state.y_compile_err = Some(e1);
-                        }
-                    }
+                ForceLaw::Yukawa(range) => {
+                    ui.horizontal(|ui| {
+                        ui.label("Range");
+                        ui.add(egui::DragValue::new(range).clamp_range(1.0..=f64::INFINITY));
+                    });
                 }
             }
         }
+        force_law_edit(ui, "Force Law", &mut settings.force_law);
 
-        ui.label("Gravitational Constant");
-        ui.add(egui::DragValue::new(&mut settings.gravitational_constant));
+        ui.horizontal(|ui| {
+            ui.label("Softening");
+            ui.add(egui::DragValue::new(&mut settings.softening).speed(0.1).clamp_range(0.0..=f64::INFINITY));
+        });
+
+        let mut curr = settings.integrator.as_str();
+        egui::ComboBox::from_label("Integrator")
+            .selected_text(curr)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut curr, "Position Verlet", "Position Verlet");
+                ui.selectable_value(&mut curr, "Velocity Verlet", "Velocity Verlet");
+                ui.selectable_value(&mut curr, "Symplectic Euler", "Symplectic Euler");
+                ui.selectable_value(&mut curr, "Forest-Ruth", "Forest-Ruth");
+                ui.selectable_value(&mut curr, "RK4", "RK4");
+            });
+        settings.integrator = Integrator::from_str(curr, settings.integrator);
+
+        ui.horizontal(|ui| {
+            ui.label("Linear Drag");
+            ui.add(egui::DragValue::new(&mut settings.drag.linear).speed(0.01).clamp_range(0.0..=f64::INFINITY));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Quadratic Drag");
+            ui.add(egui::DragValue::new(&mut settings.drag.quadratic).speed(0.0001).clamp_range(0.0..=f64::INFINITY));
+        });
 
         ui.checkbox(&mut settings.gravity_set_velocity, "Set Velocity");
-        ui.checkbox(&mut settings.collisions, "Collisions");
+        let mut curr = settings.collisions.as_str();
+        egui::ComboBox::from_label("Collisions")
+            .selected_text(curr)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut curr, "Off", "Off");
+                ui.selectable_value(&mut curr, "Push", "Push");
+                ui.selectable_value(&mut curr, "Merge", "Merge");
+            });
+        settings.collisions = CollisionMode::from_str(curr, settings.collisions);
+        if settings.collisions == CollisionMode::Push {
+            ui.horizontal(|ui| {
+                ui.label("Min Fragment Radius");
+                ui.add(egui::DragValue::new(&mut settings.min_fragment_radius).speed(0.1).clamp_range(0.1..=f64::INFINITY));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Max Fragments");
+                ui.add(egui::DragValue::new(&mut settings.max_fragments));
+            });
+        }
+        ui.checkbox(&mut settings.collision_events, "Collision Events");
+        ui.checkbox(&mut settings.sleeping, "Sleeping");
+        if settings.sleeping {
+            ui.horizontal(|ui| {
+                ui.label("Sleep Speed");
+                ui.add(egui::DragValue::new(&mut settings.sleep_speed).clamp_range(0.0..=f64::INFINITY));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Sleep Steps");
+                ui.add(egui::DragValue::new(&mut settings.sleep_steps));
+            });
+        }
+        ui.checkbox(&mut settings.deterministic, "Deterministic");
+        ui.horizontal(|ui| {
+            ui.label("Seed");
+            ui.add(egui::DragValue::new(&mut settings.seed));
+            if ui.button("Reseed").clicked() {
+                rng.reseed(settings.seed);
+            }
+        });
 
         if ui.button("Stop All").clicked() {
             objects.iter_mut().for_each(|(_, mut o)| o.old = o.current);
@@ -193,20 +435,131 @@ pub fn ui(
         if ui.button("Remove Points").clicked() {
             points.iter().for_each(|(e, _)| commands.entity(e).despawn())
         }
+        if ui.button("Remove Walls").clicked() {
+            walls.iter().for_each(|e| commands.entity(e).despawn_recursive())
+        }
+        ui.checkbox(&mut settings.adaptive_sub_steps, "Adaptive Sub Steps");
+        if settings.adaptive_sub_steps {
+            ui.horizontal(|ui| {
+                ui.label("Max Sub Steps");
+                ui.add(egui::Slider::new(&mut settings.max_sub_steps, 1..=256).logarithmic(true));
+            });
+            ui.horizontal(|ui| {
+                ui.label("CFL");
+                ui.add(egui::Slider::new(&mut settings.cfl, 0.05..=2.0));
+            });
+        } else {
+            ui.horizontal(|ui| {
+                ui.label("Sub Steps");
+                let mut value = u32::from(settings.sub_steps);
+                ui.add(egui::Slider::new(&mut value, 1..=32));
+                if let Some(value) = NonZeroU32::new(value) {
+                    settings.sub_steps = value;
+                }
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.label("Tick Rate");
+            ui.add(egui::DragValue::new(&mut settings.tick_rate).clamp_range(1.0..=1000.0));
+        });
         ui.horizontal(|ui| {
-            ui.label("Sub Steps");
-            let mut value = u32::from(settings.sub_steps);
-            ui.add(egui::Slider::new(&mut value, 1..=32));
-            if let Some(value) = NonZeroU32::new(value) {
-                settings.sub_steps = value;
+            ui.label("Max Ticks");
+            ui.add(egui::Slider::new(&mut settings.max_ticks, 1..=16));
+        });
+        ui.horizontal(|ui| {
+            ui.label(format!("Time: {:.2} s", settings.time));
+            if ui.button("Reset").clicked() {
+                settings.time = 0.0;
             }
         });
 
+        ui.heading("Gravity Wells");
+        ui.label("Press G to place one, drag with the left mouse button.");
+        for (e, mut well) in wells.iter_mut() {
+            ui.push_id(e, |ui| {
+                ui.separator();
+                scalar(ui, "Strength", &mut well.strength);
+                force_law_edit(ui, "Falloff", &mut well.law);
+                let mut curr = well.motion.as_str();
+                egui::ComboBox::from_label("Motion")
+                    .selected_text(curr)
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut curr, "Pinned", "Pinned");
+                        ui.selectable_value(&mut curr, "Moving", "Moving");
+                        ui.selectable_value(&mut curr, "Following", "Following");
+                    });
+                if curr != well.motion.as_str() {
+                    // Following picks the object closest to the well.
+                    let pos = well.pos;
+                    let closest = || {
+                        objects
+                            .iter()
+                            .min_by(|(_, a), (_, b)| {
+                                a.current.distance_squared(pos).total_cmp(&b.current.distance_squared(pos))
+                            })
+                            .map(|(e, _)| e)
+                    };
+                    well.motion = match curr {
+                        "Pinned" => WellMotion::Pinned,
+                        "Moving" => WellMotion::Moving(DVec2::ZERO),
+                        _ => closest().map_or(well.motion, WellMotion::Following),
+                    };
+                }
+                if let WellMotion::Moving(velocity) = &mut well.motion {
+                    vector(ui, "Velocity", velocity);
+                }
+                if ui.button("Remove").clicked() {
+                    commands.entity(e).despawn();
+                }
+            });
+        }
+
         ui.heading("Placement");
         ui.label("Radius");
         ui.add(egui::Slider::new(&mut placement.radius, 1.0..=4000.0).logarithmic(true));
         ui.label("Density");
         ui.add(egui::Slider::new(&mut placement.density, 0.1..=1000.0).logarithmic(true));
+        ui.label("Restitution");
+        ui.add(egui::Slider::new(&mut placement.material.restitution, 0.0..=1.0));
+        ui.label("Friction");
+        ui.add(egui::Slider::new(&mut placement.material.friction, 0.0..=2.0));
+        scalar(ui, "Charge", &mut placement.charge);
+        let mut breakable = placement.material.strength.is_finite();
+        ui.checkbox(&mut breakable, "Breakable");
+        if breakable != placement.material.strength.is_finite() {
+            placement.material.strength = if breakable { 500.0 } else { f64::INFINITY };
+        }
+        if breakable {
+            ui.horizontal(|ui| {
+                ui.label("Strength");
+                ui.add(egui::DragValue::new(&mut placement.material.strength).clamp_range(0.0..=f64::MAX));
+            });
+        }
+        let mut override_drag = placement.drag.is_some();
+        ui.checkbox(&mut override_drag, "Override Drag");
+        let drag = placement.drag.unwrap_or(settings.drag);
+        placement.drag = override_drag.then_some(drag);
+        if let Some(drag) = &mut placement.drag {
+            ui.horizontal(|ui| {
+                ui.label("Linear");
+                ui.add(egui::DragValue::new(&mut drag.linear).speed(0.01).clamp_range(0.0..=f64::INFINITY));
+                ui.label("Quadratic");
+                ui.add(egui::DragValue::new(&mut drag.quadratic).speed(0.0001).clamp_range(0.0..=f64::INFINITY));
+            });
+        }
+        let mut curr = placement.kind.as_str();
+        egui::ComboBox::from_label("Kind")
+            .selected_text(curr)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut curr, "Dynamic", "Dynamic");
+                ui.selectable_value(&mut curr, "Static", "Static");
+                ui.selectable_value(&mut curr, "Kinematic", "Kinematic");
+            });
+        placement.kind = BodyKind::from_str(curr, placement.kind);
+        if let BodyKind::Kinematic(velocity) = &mut placement.kind {
+            vector(ui, "Velocity", velocity);
+        }
+        ui.checkbox(&mut placement.ccd, "Continuous Collisions");
         fn color_edit(ui: &mut egui::Ui, color: &mut Color) -> egui::Response {
             match *color {
                 Color::Rgba {
@@ -250,6 +603,27 @@ pub fn ui(
         
         ui.label(format!("Bodies: {}", objects.iter().count()));
         ui.label(format!("Links: {}", links.iter().count()));
+        ui.label(format!("Walls: {}", walls.iter().count()));
+        ui.label(format!("Sub Steps: {}", phys_time.sub_steps));
+        let (contacts, max_speed) = collision_events
+            .iter()
+            .fold((0, 0.0f64), |(n, speed), e| (n + 1, speed.max(e.relative_speed)));
+        ui.label(format!("Contacts: {} (fastest {:.1})", contacts, max_speed));
+
+        let d = &*phys_diagnostics;
+        ui.label(format!(
+            "Energy: {:.4e} (kinetic {:.4e}, potential {:.4e})",
+            d.total_energy(),
+            d.kinetic_energy,
+            d.potential_energy
+        ));
+        ui.label(format!("Max Link Stretch: {:.4}", d.max_link_stretch));
+        ui.label(format!("Momentum: ({:.4e}, {:.4e})", d.momentum.x, d.momentum.y));
+        ui.label(format!("Angular Momentum: {:.4e}", d.angular_momentum));
+        ui.label(format!(
+            "Center of Mass: ({:.1}, {:.1})",
+            d.center_of_mass.x, d.center_of_mass.y
+        ));
 
         let fps_diags = diagnostics
             .get(FrameTimeDiagnosticsPlugin::FPS)